@@ -0,0 +1,321 @@
+//! Cache of scan results for incremental rescans.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter},
+    path::{self, Path, PathBuf},
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{scan::EntryKind, InfoJson, MovieEntry};
+
+/// Version of the cache file format; caches of other versions are ignored.
+const VERSION: u32 = 2;
+
+/// Files and directories modified this close to the start of a scan may
+/// change again without their modification time changing on file systems
+/// with coarse timestamps, so they are not cached.
+const RACY_WINDOW: Duration = Duration::from_secs(2);
+
+/// Results of previous scans, used to skip files and directories that have
+/// not changed since.
+///
+/// Files are keyed by absolute path and their entries are reused as long as
+/// their size and modification time are unchanged. Directories whose
+/// modification time is unchanged are not listed again and their previous
+/// listing is used instead, but the files and subdirectories in it are still
+/// checked individually.
+///
+/// Entries are only reused by scanners with the same settings as the one
+/// that produced them, and from the same root if their user depends on it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ScanCache {
+    version: u32,
+    /// Settings of the scanner the entries were produced with.
+    settings: String,
+    dirs: HashMap<String, CachedDir>,
+    files: HashMap<String, CachedFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedDir {
+    mtime: u64,
+    children: Vec<CachedChild>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CachedChild {
+    pub(crate) name: String,
+    pub(crate) kind: EntryKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedFile {
+    #[serde(flatten)]
+    stamp: Stamp,
+    entry: MovieEntry,
+}
+
+/// State of a file that its entry depends on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Stamp {
+    size: u64,
+    mtime: u64,
+    /// Modification time of the `.info.json` sidecar, if there is one and
+    /// sidecars are read.
+    sidecar: Option<u64>,
+    /// Root of the scan the file was reached from, only for entries whose
+    /// user may depend on it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    root: Option<String>,
+}
+
+impl Stamp {
+    /// Reads the state of a file, returning `None` if it is unavailable.
+    ///
+    /// `root` is given when the entry depends on the root of the scan, as
+    /// with [`UserRule::Depth`](crate::UserRule::Depth), so that entries are
+    /// not reused from scans of other roots.
+    pub(crate) fn read(path: &Path, info_json: bool, root: Option<&Path>) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        let sidecar = match info_json {
+            true => fs::metadata(InfoJson::sidecar_path(path))
+                .ok()
+                .and_then(|m| mtime(&m)),
+            false => None,
+        };
+        Some(Self {
+            size: metadata.len(),
+            mtime: mtime(&metadata)?,
+            sidecar,
+            root: root.and_then(key),
+        })
+    }
+}
+
+impl ScanCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads a cache file, returning an empty cache if it does not exist or
+    /// was written by an incompatible version.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e),
+        };
+        let cache: Self = serde_json::from_reader(BufReader::new(file))?;
+        Ok(match cache.version {
+            VERSION => cache,
+            _ => Self::default(),
+        })
+    }
+
+    /// Writes the cache to a file, replacing it atomically.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        serde_json::to_writer(&mut out, self)?;
+        out.into_inner()
+            .map_err(io::IntoInnerError::into_error)?
+            .sync_all()?;
+        fs::rename(&tmp, path)
+    }
+}
+
+/// State of a cache during a scan: the previous results being consumed and
+/// the new ones being recorded.
+#[derive(Debug)]
+pub(crate) struct CacheState {
+    old: ScanCache,
+    new: ScanCache,
+    /// Modification times from this point on are considered racy.
+    racy_since: u64,
+}
+
+impl CacheState {
+    pub(crate) fn new(mut old: ScanCache, settings: String) -> Self {
+        // Entries depend on the settings, but directory listings do not.
+        if old.settings != settings {
+            old.files.clear();
+        }
+        let new = ScanCache {
+            version: VERSION,
+            settings,
+            ..ScanCache::default()
+        };
+        let racy_since = nanos(SystemTime::now() - RACY_WINDOW).unwrap_or(0);
+        Self {
+            old,
+            new,
+            racy_since,
+        }
+    }
+
+    /// Returns the previous listing of a directory if it has not been
+    /// modified since, recording it for the new cache.
+    pub(crate) fn listing(&mut self, dir: &Path, mtime: u64) -> Option<Vec<CachedChild>> {
+        let key = key(dir)?;
+        let cached = self.old.dirs.remove(&key)?;
+        if cached.mtime != mtime {
+            return None;
+        }
+        let children = cached.children.clone();
+        self.new.dirs.insert(key, cached);
+        Some(children)
+    }
+
+    /// Records a directory listing read from the file system, provided that
+    /// its modification time was taken before it was read.
+    pub(crate) fn insert_listing(&mut self, dir: &Path, mtime: u64, children: Vec<CachedChild>) {
+        if let Some(key) = key(dir).filter(|_| mtime < self.racy_since) {
+            self.new.dirs.insert(key, CachedDir { mtime, children });
+        }
+    }
+
+    /// Returns the previous entry of a file if its state is still `stamp`.
+    pub(crate) fn entry(&mut self, path: &Path, stamp: &Stamp) -> Option<MovieEntry> {
+        let key = key(path)?;
+        let cached = self.old.files.remove(&key)?;
+        if *stamp != cached.stamp {
+            return None;
+        }
+        let mut entry = cached.entry.clone();
+        entry.path = path.to_path_buf();
+        self.new.files.insert(key, cached);
+        Some(entry)
+    }
+
+    pub(crate) fn insert_entry(&mut self, entry: &MovieEntry, stamp: Stamp) {
+        let racy = stamp.mtime.max(stamp.sidecar.unwrap_or(0)) >= self.racy_since;
+        if let Some(key) = key(&entry.path).filter(|_| !racy) {
+            let entry = entry.clone();
+            self.new.files.insert(key, CachedFile { stamp, entry });
+        }
+    }
+
+    /// Returns the new cache, keeping previous results outside the scanned
+    /// roots.
+    pub(crate) fn finish(self, roots: &[PathBuf]) -> ScanCache {
+        let Self { old, mut new, .. } = self;
+        let roots = roots
+            .iter()
+            .filter_map(|root| path::absolute(root).ok())
+            .collect::<Vec<_>>();
+        let outside = |key: &String| !roots.iter().any(|root| Path::new(key).starts_with(root));
+        new.dirs
+            .extend(old.dirs.into_iter().filter(|(key, _)| outside(key)));
+        new.files
+            .extend(old.files.into_iter().filter(|(key, _)| outside(key)));
+        new
+    }
+}
+
+/// Locks a cache shared between threads. A panic while holding the lock
+/// leaves the cache consistent, so poisoning is ignored.
+pub(crate) fn lock(cache: &Mutex<CacheState>) -> MutexGuard<'_, CacheState> {
+    cache.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Returns the cache key of a path, or `None` if it cannot be cached.
+fn key(path: &Path) -> Option<String> {
+    path::absolute(path)
+        .ok()?
+        .into_os_string()
+        .into_string()
+        .ok()
+}
+
+pub(crate) fn mtime(metadata: &fs::Metadata) -> Option<u64> {
+    nanos(metadata.modified().ok()?)
+}
+
+fn nanos(time: SystemTime) -> Option<u64> {
+    let nanos = time.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+    nanos.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{probe::sample_mp4, testing::TempDir, Integrity, Scanner};
+
+    #[test]
+    fn reuse_of_unchanged_files() {
+        let root = TempDir::new("cache");
+        let dir = root.join("@user");
+        let path = dir.join("Title [abc].mp4");
+        fs::create_dir_all(&dir).unwrap();
+        let write = |data: &[u8], mtime: SystemTime| {
+            fs::write(&path, data).unwrap();
+            let file = File::options().write(true).open(&path).unwrap();
+            file.set_modified(mtime).unwrap();
+        };
+        let scanner = Scanner::new().verify(true);
+        let scan = |cache: ScanCache| {
+            let mut scan = scanner.scan([&root]).with_cache(cache);
+            let statuses = scan
+                .by_ref()
+                .map(|result| result.unwrap().integrity.unwrap().status)
+                .collect::<Vec<_>>();
+            (statuses, scan.into_cache().unwrap())
+        };
+
+        let data = sample_mp4();
+        let past = SystemTime::now() - Duration::from_secs(60);
+        write(&data, past);
+        // Directories out of the racy window have their listings cached.
+        for dir in [&*root, &dir] {
+            File::open(dir).unwrap().set_modified(past).unwrap();
+        }
+        let (statuses, cache) = scan(ScanCache::new());
+        assert_eq!(statuses, [Integrity::Ok]);
+        assert_eq!(cache.dirs.len(), 2);
+
+        // Changes that keep the size and modification time go unnoticed.
+        write(&vec![0; data.len()], past);
+        let (statuses, cache) = scan(cache);
+        assert_eq!(statuses, [Integrity::Ok]);
+
+        write(&vec![0; data.len()], past + Duration::from_secs(1));
+        let (statuses, _) = scan(cache);
+        assert_eq!(statuses, [Integrity::Corrupt]);
+    }
+
+    #[test]
+    fn depth_rules_across_roots() {
+        let root = TempDir::new("cache-depth");
+        let dir = root.join("chan").join("sub");
+        let path = dir.join("Title [abc].mp4");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, b"").unwrap();
+        let past = SystemTime::now() - Duration::from_secs(60);
+        let file = File::options().write(true).open(&path).unwrap();
+        file.set_modified(past).unwrap();
+        let scanner = Scanner::new().user_rules(vec!["depth=1".parse().unwrap()]);
+        let scan = |root: &Path, cache: ScanCache| {
+            let mut scan = scanner.scan([root]).with_cache(cache);
+            let users = scan
+                .by_ref()
+                .map(|result| result.unwrap().user)
+                .collect::<Vec<_>>();
+            (users, scan.into_cache().unwrap())
+        };
+
+        let (users, cache) = scan(&root, ScanCache::new());
+        assert_eq!(users, ["chan"]);
+        assert_eq!(cache.files.len(), 1);
+        let (users, cache) = scan(&root.join("chan"), cache);
+        assert_eq!(users, ["sub"]);
+        let (users, _) = scan(&root, cache);
+        assert_eq!(users, ["chan"]);
+    }
+}
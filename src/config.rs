@@ -0,0 +1,178 @@
+use std::{fmt, fs, io, path::Path};
+
+use serde::Deserialize;
+
+use crate::{Extensions, Template, TemplateError, UserRule};
+
+/// Settings loaded from a TOML config file.
+///
+/// ```toml
+/// extensions = ["video", "audio"]
+/// user_rules = ["prefix=@", 'regex=^UC[\w-]{22}$', "literal=unknown"]
+///
+/// [[templates]]
+/// name = "bracket"
+///
+/// [[templates]]
+/// name = "id-title"
+/// format = "%(id)s - %(title)s"
+///
+/// [[templates]]
+/// name = "dated"
+/// regex = '^(?<upload_date>\d{8})_(?<title>.+)_(?<id>[^_]+)$'
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Extensions of the files to list, or names of presets of
+    /// [`Extensions`].
+    pub extensions: Vec<String>,
+    /// Filename templates tried in order. Entries with neither `format` nor
+    /// `regex` refer to built-in templates by name.
+    pub templates: Vec<TemplateConfig>,
+    /// Rules tried in order to find the user of a movie, as parsed by
+    /// [`UserRule`].
+    pub user_rules: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateConfig {
+    pub name: String,
+    pub format: Option<String>,
+    pub regex: Option<String>,
+}
+
+impl Config {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let s = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        toml::from_str(&s).map_err(ConfigError::Toml)
+    }
+
+    /// Builds the list of extensions, or returns `None` if none are
+    /// configured.
+    pub fn extensions(&self) -> Result<Option<Extensions>, ConfigError> {
+        if self.extensions.is_empty() {
+            return Ok(None);
+        }
+        Extensions::from_items(&self.extensions)
+            .map(Some)
+            .map_err(ConfigError::Invalid)
+    }
+
+    /// Builds the user rules, or returns `None` if none are configured.
+    pub fn user_rules(&self) -> Result<Option<Vec<UserRule>>, ConfigError> {
+        if self.user_rules.is_empty() {
+            return Ok(None);
+        }
+        let rules = self
+            .user_rules
+            .iter()
+            .map(|rule| rule.parse())
+            .collect::<Result<_, _>>()
+            .map_err(ConfigError::Invalid)?;
+        Ok(Some(rules))
+    }
+
+    /// Builds the filename templates, or returns `None` if none are
+    /// configured.
+    pub fn templates(&self) -> Result<Option<Vec<Template>>, ConfigError> {
+        if self.templates.is_empty() {
+            return Ok(None);
+        }
+        let templates = self
+            .templates
+            .iter()
+            .map(|t| t.build())
+            .collect::<Result<_, _>>()?;
+        Ok(Some(templates))
+    }
+}
+
+impl TemplateConfig {
+    fn build(&self) -> Result<Template, ConfigError> {
+        let name = &self.name;
+        let result = match (&self.format, &self.regex) {
+            (Some(format), None) => Template::from_format(name, format),
+            (None, Some(regex)) => Template::from_regex(name, regex),
+            (None, None) => {
+                return Template::preset(name)
+                    .ok_or_else(|| ConfigError::UnknownTemplate(name.clone()));
+            }
+            (Some(_), Some(_)) => {
+                return Err(ConfigError::Invalid(format!(
+                    "template {name:?} has both `format` and `regex`"
+                )));
+            }
+        };
+        result.map_err(|source| ConfigError::Template {
+            name: name.clone(),
+            source,
+        })
+    }
+}
+
+/// Errors in loading or interpreting a [`Config`].
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Toml(toml::de::Error),
+    Template { name: String, source: TemplateError },
+    UnknownTemplate(String),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "config file could not be read: {e}"),
+            Self::Toml(e) => write!(f, "config file could not be parsed: {e}"),
+            Self::Template { name, source } => write!(f, "template {name:?}: {source}"),
+            Self::UnknownTemplate(name) => write!(f, "unknown built-in template: {name:?}"),
+            Self::Invalid(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Toml(e) => Some(e),
+            Self::Template { source, .. } => Some(source),
+            Self::UnknownTemplate(_) | Self::Invalid(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_from_toml() {
+        let config: Config = toml::from_str(
+            r#"
+            [[templates]]
+            name = "bracket"
+
+            [[templates]]
+            name = "dated"
+            regex = '^(?<upload_date>\d{8})_(?<title>.+)_(?<id>[^_]+)$'
+            "#,
+        )
+        .unwrap();
+        let templates = config.templates().unwrap().unwrap();
+        let names = templates.iter().map(Template::name).collect::<Vec<_>>();
+        assert_eq!(names, ["bracket", "dated"]);
+    }
+
+    #[test]
+    fn unknown_preset_is_rejected() {
+        let config: Config = toml::from_str("[[templates]]\nname = \"nope\"").unwrap();
+        assert!(matches!(
+            config.templates(),
+            Err(ConfigError::UnknownTemplate(_))
+        ));
+    }
+}
@@ -0,0 +1,307 @@
+use std::{
+    fmt,
+    ops::Range,
+    str::FromStr,
+    sync::LazyLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use regex::Regex;
+
+/// Calendar date, serialized in ISO 8601 form (`YYYY-MM-DD`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl Date {
+    /// Creates a date, returning `None` if it does not exist in the calendar.
+    pub fn new(year: u16, month: u8, day: u8) -> Option<Self> {
+        let leap =
+            year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+        let days = match month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 if leap => 29,
+            2 => 28,
+            _ => return None,
+        };
+        (1..=days)
+            .contains(&day)
+            .then_some(Self { year, month, day })
+    }
+
+    /// Parses a date in the `YYYYMMDD` form used by yt-dlp's `upload_date`.
+    pub fn from_compact(s: &str) -> Option<Self> {
+        if s.len() != 8 || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        Self::new(
+            s[..4].parse().ok()?,
+            s[4..6].parse().ok()?,
+            s[6..].parse().ok()?,
+        )
+    }
+
+    /// Finds the first date in a title, returning it along with the byte
+    /// range it occupies.
+    ///
+    /// Recognized forms are `YYYY年M月D日`, `YYYY-MM-DD`, `YYYY/MM/DD` and
+    /// `YYYYMMDD`. Digit sequences that are part of longer numbers and
+    /// impossible dates are skipped.
+    pub fn find_in(title: &str) -> Option<(Self, Range<usize>)> {
+        static RE: LazyLock<Regex> = LazyLock::new(|| {
+            Regex::new(concat!(
+                r"(?<y1>[0-9]{4})年\s*(?<m1>[0-9]{1,2})月\s*(?<d1>[0-9]{1,2})日",
+                r"|(?<y2>[0-9]{4})-(?<m2>[0-9]{1,2})-(?<d2>[0-9]{1,2})",
+                r"|(?<y3>[0-9]{4})/(?<m3>[0-9]{1,2})/(?<d3>[0-9]{1,2})",
+                r"|(?<y4>[0-9]{4})(?<m4>[0-9]{2})(?<d4>[0-9]{2})",
+            ))
+            .unwrap()
+        });
+        let is_digit_at = |i: Option<char>| i.is_some_and(|c| c.is_ascii_digit());
+        RE.captures_iter(title).find_map(|caps| {
+            let range = caps.get(0)?.range();
+            if is_digit_at(title[..range.start].chars().next_back())
+                || is_digit_at(title[range.end..].chars().next())
+            {
+                return None;
+            }
+            let (y, m, d) = ["1", "2", "3", "4"].iter().find_map(|n| {
+                Some((
+                    caps.name(&format!("y{n}"))?,
+                    caps.name(&format!("m{n}"))?,
+                    caps.name(&format!("d{n}"))?,
+                ))
+            })?;
+            let date = Self::new(
+                y.as_str().parse().ok()?,
+                m.as_str().parse().ok()?,
+                d.as_str().parse().ok()?,
+            )?;
+            Some((date, range))
+        })
+    }
+}
+
+/// Removes the date at `range` from a title, together with brackets that
+/// enclose nothing but the date, and tidies up the surrounding spaces.
+pub(crate) fn strip_date(title: &str, range: Range<usize>) -> String {
+    const PAIRS: [(char, char); 5] = [
+        ('(', ')'),
+        ('（', '）'),
+        ('[', ']'),
+        ('【', '】'),
+        ('「', '」'),
+    ];
+
+    let (mut start, mut end) = (range.start, range.end);
+    let before = title[..start].trim_end_matches(' ');
+    let after = title[end..].trim_start_matches(' ');
+    for (open, close) in PAIRS {
+        if let (Some(b), Some(a)) = (before.strip_suffix(open), after.strip_prefix(close)) {
+            start = b.len();
+            end = title.len() - a.len();
+            break;
+        }
+    }
+    let (before, after) = (title[..start].trim_end(), title[end..].trim_start());
+    match (before.is_empty(), after.is_empty()) {
+        (false, false) => format!("{before} {after}"),
+        _ => format!("{before}{after}"),
+    }
+}
+
+/// Point in time with one-second precision, serialized in RFC 3339 form in
+/// UTC (`YYYY-MM-DDThh:mm:ssZ`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp {
+    /// Seconds since the Unix epoch.
+    pub secs: i64,
+}
+
+impl Timestamp {
+    pub fn from_secs(secs: i64) -> Self {
+        Self { secs }
+    }
+}
+
+impl From<SystemTime> for Timestamp {
+    fn from(time: SystemTime) -> Self {
+        let secs = match time.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs_f64().ceil() as i64),
+        };
+        Self { secs }
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Converts days since the epoch to a civil date with the algorithm
+        // from http://howardhinnant.github.io/date_algorithms.html.
+        let (days, secs) = (self.secs.div_euclid(86400), self.secs.rem_euclid(86400));
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + i64::from(month <= 2);
+        write!(
+            f,
+            "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+            secs / 3600,
+            secs % 3600 / 60,
+            secs % 60
+        )
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = ParseDateError;
+
+    /// Parses the `YYYY-MM-DDThh:mm:ssZ` form produced by `Display`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (date, time) = s.split_once('T').ok_or(ParseDateError)?;
+        let time = time.strip_suffix('Z').ok_or(ParseDateError)?;
+        let Date { year, month, day } = date.parse()?;
+        let mut parts = time.split(':').map(|part| match part.len() {
+            2 => part.parse::<i64>().map_err(|_| ParseDateError),
+            _ => Err(ParseDateError),
+        });
+        let (Some(h), Some(m), Some(sec), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(ParseDateError);
+        };
+        let (h, m, sec) = (h?, m?, sec?);
+        if h > 23 || m > 59 || sec > 59 {
+            return Err(ParseDateError);
+        }
+        // Inverse of the conversion in `Display`.
+        let (month, day) = (i64::from(month), i64::from(day));
+        let year = i64::from(year) - i64::from(month <= 2);
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let mp = if month > 2 { month - 3 } else { month + 9 };
+        let doy = (153 * mp + 2) / 5 + day - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146097 + doe - 719468;
+        Ok(Self::from_secs(days * 86400 + h * 3600 + m * 60 + sec))
+    }
+}
+
+impl serde::Serialize for Timestamp {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Timestamp {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<str>>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl FromStr for Date {
+    type Err = ParseDateError;
+
+    /// Parses the `YYYY-MM-DD` form produced by `Display`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = s.as_bytes();
+        if s.len() != 10 || bytes[4] != b'-' || bytes[7] != b'-' {
+            return Err(ParseDateError);
+        }
+        let compact = [&s[..4], &s[5..7], &s[8..]].concat();
+        Self::from_compact(&compact).ok_or(ParseDateError)
+    }
+}
+
+impl serde::Serialize for Date {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Date {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<str>>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Error returned when a [`Date`] or [`Timestamp`] string is malformed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDateError;
+
+impl fmt::Display for ParseDateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("malformed date or timestamp")
+    }
+}
+
+impl std::error::Error for ParseDateError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_in_titles() {
+        let cases = [
+            ("@FooBar (2024年11月1日)", Some("2024-11-01"), "@FooBar"),
+            ("Live 2023-02-28 part 2", Some("2023-02-28"), "Live part 2"),
+            ("【2020/2/29】Stream", Some("2020-02-29"), "Stream"),
+            ("20240101 title", Some("2024-01-01"), "title"),
+            ("Episode 120240101", None, ""),
+            ("2023-02-29", None, ""),
+        ];
+        for (title, expected_date, expected_stripped) in cases {
+            let found = Date::find_in(title);
+            let date = found.as_ref().map(|(date, _)| date.to_string());
+            assert_eq!(date.as_deref(), expected_date, "{title}");
+            if let Some((_, range)) = found {
+                assert_eq!(strip_date(title, range), expected_stripped, "{title}");
+            }
+        }
+    }
+
+    #[test]
+    fn timestamps() {
+        let cases = [
+            (0, "1970-01-01T00:00:00Z"),
+            (951782400, "2000-02-29T00:00:00Z"),
+            (1730464496, "2024-11-01T12:34:56Z"),
+            (-1, "1969-12-31T23:59:59Z"),
+        ];
+        for (secs, expected) in cases {
+            assert_eq!(Timestamp::from_secs(secs).to_string(), expected);
+            assert_eq!(expected.parse(), Ok(Timestamp::from_secs(secs)));
+        }
+        assert_eq!(
+            "2024-11-01 12:34:56".parse::<Timestamp>(),
+            Err(ParseDateError)
+        );
+    }
+
+    #[test]
+    fn compact_dates() {
+        assert_eq!(Date::from_compact("20241101"), Date::new(2024, 11, 1));
+        assert_eq!(Date::from_compact("2024110"), None);
+        assert_eq!(Date::from_compact("20241301"), None);
+        assert_eq!("2024-11-01".parse(), Ok(Date::new(2024, 11, 1).unwrap()));
+        assert_eq!("2024-1-01".parse::<Date>(), Err(ParseDateError));
+    }
+}
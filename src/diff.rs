@@ -0,0 +1,219 @@
+use std::{collections::HashMap, fmt, path::Path};
+
+use serde_json::Value;
+
+/// Kind of difference between two sets of entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    /// The file moved to another `@user` directory.
+    Moved,
+    TitleChanged,
+    ExtensionChanged,
+}
+
+/// Difference found for an ID by [`diff`].
+///
+/// `user`, `title` and `path` describe the entry in the new set, or in the
+/// old set for removals. `old` and `new` hold the user, title or extension
+/// before and after for changes of the respective kind.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Change {
+    #[serde(rename = "change")]
+    pub kind: ChangeKind,
+    pub id: String,
+    pub user: String,
+    pub title: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+    pub path: Option<String>,
+}
+
+/// Fields of an entry compared by [`diff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Key {
+    id: String,
+    user: String,
+    title: String,
+    extension: Option<String>,
+    path: Option<String>,
+}
+
+impl Key {
+    /// Reads the fields from a serialized entry, taking the extension from
+    /// the `extension` field or, failing that, the `path` field.
+    fn from_value(value: &Value) -> Result<Self, DiffError> {
+        let field = |name| value.get(name).and_then(Value::as_str).map(str::to_owned);
+        let id = field("id").ok_or(DiffError::MissingId)?;
+        let path = field("path").or_else(|| field("relative_path"));
+        let extension = field("extension").or_else(|| {
+            let ext = Path::new(path.as_deref()?).extension()?;
+            Some(ext.to_string_lossy().to_lowercase())
+        });
+        Ok(Self {
+            id,
+            user: field("user").unwrap_or_default(),
+            title: field("title").unwrap_or_default(),
+            extension,
+            path,
+        })
+    }
+
+    fn same_file(&self, other: &Self) -> bool {
+        self.user == other.user && self.title == other.title && self.extension == other.extension
+    }
+
+    fn change(&self, kind: ChangeKind, old: Option<&str>, new: Option<&str>) -> Change {
+        Change {
+            kind,
+            id: self.id.clone(),
+            user: self.user.clone(),
+            title: self.title.clone(),
+            old: old.map(str::to_owned),
+            new: new.map(str::to_owned),
+            path: self.path.clone(),
+        }
+    }
+}
+
+/// Compares two sets of serialized entries, such as the output of two scans,
+/// matching them by ID.
+///
+/// Entries are JSON objects with at least an `id` field. Extension changes
+/// are only detected between entries with an `extension` or `path` field.
+/// When an ID has several files, unchanged files are matched first and the
+/// rest in order. Changes are reported in the order IDs first appear.
+pub fn diff(old: &[Value], new: &[Value]) -> Result<Vec<Change>, DiffError> {
+    let mut ids = Vec::new();
+    let mut groups = HashMap::<String, (Vec<Key>, Vec<Key>)>::new();
+    for (values, is_new) in [(old, false), (new, true)] {
+        for value in values {
+            let key = Key::from_value(value)?;
+            let group = groups.entry(key.id.clone()).or_insert_with(|| {
+                ids.push(key.id.clone());
+                Default::default()
+            });
+            match is_new {
+                false => group.0.push(key),
+                true => group.1.push(key),
+            }
+        }
+    }
+
+    let mut changes = Vec::new();
+    for id in ids {
+        let (mut olds, mut news) = groups.remove(&id).unwrap();
+        olds.retain(|old| match news.iter().position(|new| new.same_file(old)) {
+            Some(i) => {
+                news.remove(i);
+                false
+            }
+            None => true,
+        });
+        let paired = olds.len().min(news.len());
+        for (old, new) in olds.iter().zip(&news) {
+            if old.user != new.user {
+                changes.push(new.change(ChangeKind::Moved, Some(&old.user), Some(&new.user)));
+            }
+            if old.title != new.title {
+                let (o, n) = (Some(old.title.as_str()), Some(new.title.as_str()));
+                changes.push(new.change(ChangeKind::TitleChanged, o, n));
+            }
+            if let (Some(o), Some(n)) = (&old.extension, &new.extension) {
+                if o != n {
+                    changes.push(new.change(ChangeKind::ExtensionChanged, Some(o), Some(n)));
+                }
+            }
+        }
+        for old in &olds[paired..] {
+            changes.push(old.change(ChangeKind::Removed, None, None));
+        }
+        for new in &news[paired..] {
+            changes.push(new.change(ChangeKind::Added, None, None));
+        }
+    }
+    Ok(changes)
+}
+
+/// Error returned by [`diff`] for entries that cannot be compared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffError {
+    MissingId,
+}
+
+impl fmt::Display for DiffError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiffError::MissingId => f.write_str("entry without an id field"),
+        }
+    }
+}
+
+impl std::error::Error for DiffError {}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn changes() {
+        let old = [
+            json!({"id": "a", "user": "alice", "title": "Same", "path": "/m/@alice/Same [a].mp4"}),
+            json!({"id": "b", "user": "alice", "title": "Moved", "extension": "mkv"}),
+            json!({"id": "c", "user": "bob", "title": "Old title", "extension": "webm"}),
+            json!({"id": "d", "user": "bob", "title": "Gone"}),
+        ];
+        let new = [
+            json!({"id": "e", "user": "carol", "title": "New"}),
+            json!({"id": "c", "user": "bob", "title": "New title", "extension": "mp4"}),
+            json!({"id": "b", "user": "carol", "title": "Moved", "extension": "mkv"}),
+            json!({"id": "a", "user": "alice", "title": "Same", "path": "/m/@alice/Same [a].mp4"}),
+        ];
+        let summary = diff(&old, &new)
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.kind, c.id, c.old, c.new))
+            .collect::<Vec<_>>();
+        let s = |s: &str| Some(s.to_owned());
+        let expected = [
+            (ChangeKind::Moved, "b".to_owned(), s("alice"), s("carol")),
+            (
+                ChangeKind::TitleChanged,
+                "c".to_owned(),
+                s("Old title"),
+                s("New title"),
+            ),
+            (
+                ChangeKind::ExtensionChanged,
+                "c".to_owned(),
+                s("webm"),
+                s("mp4"),
+            ),
+            (ChangeKind::Removed, "d".to_owned(), None, None),
+            (ChangeKind::Added, "e".to_owned(), None, None),
+        ];
+        assert_eq!(summary, expected);
+
+        assert_eq!(
+            diff(&[json!({"user": "x"})], &[]),
+            Err(DiffError::MissingId)
+        );
+    }
+
+    #[test]
+    fn duplicate_ids() {
+        let old = [
+            json!({"id": "a", "user": "alice", "title": "T", "extension": "mp4"}),
+            json!({"id": "a", "user": "alice", "title": "T", "extension": "webm"}),
+        ];
+        let new = [json!({"id": "a", "user": "alice", "title": "T", "extension": "webm"})];
+        let changes = diff(&old, &new).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind, ChangeKind::Removed);
+        assert_eq!(changes[0].path, None);
+    }
+}
@@ -0,0 +1,230 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{extensions, file_info::serialize_path, Error, MovieEntry, Timestamp};
+
+/// Movie files sharing the same ID.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct DuplicateGroup {
+    pub id: String,
+    /// Files in the group, the one suggested to keep first.
+    pub files: Vec<DuplicateFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct DuplicateFile {
+    #[serde(serialize_with = "serialize_path")]
+    pub path: PathBuf,
+    pub user: String,
+    pub extension: String,
+    pub size: u64,
+    pub modified: Timestamp,
+    /// Whether the file is the copy suggested to keep.
+    pub keep: bool,
+}
+
+impl DuplicateFile {
+    fn new(path: PathBuf, user: String) -> io::Result<Self> {
+        let metadata = fs::metadata(&path)?;
+        let extension = extension_of(&path);
+        Ok(Self {
+            path,
+            user,
+            extension,
+            size: metadata.len(),
+            modified: metadata.modified()?.into(),
+            keep: false,
+        })
+    }
+}
+
+fn extension_of(path: &Path) -> String {
+    let ext = extensions::extension(path).unwrap_or_default();
+    ext.to_string_lossy().to_lowercase()
+}
+
+/// Criterion for choosing which copy among duplicates to keep.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeepPreference {
+    /// Prefer containers earlier in the list of extensions.
+    Container(Vec<String>),
+    Largest,
+    Newest,
+}
+
+impl KeepPreference {
+    fn compare(&self, a: &DuplicateFile, b: &DuplicateFile) -> Ordering {
+        match self {
+            Self::Container(order) => {
+                let rank = |f: &DuplicateFile| {
+                    order
+                        .iter()
+                        .position(|ext| ext.eq_ignore_ascii_case(&f.extension))
+                        .unwrap_or(order.len())
+                };
+                rank(a).cmp(&rank(b))
+            }
+            Self::Largest => b.size.cmp(&a.size),
+            Self::Newest => b.modified.cmp(&a.modified),
+        }
+    }
+}
+
+/// Parses `largest`, `newest` or `container:EXT,EXT,...`.
+impl FromStr for KeepPreference {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "largest" => Ok(Self::Largest),
+            "newest" => Ok(Self::Newest),
+            _ => match s.strip_prefix("container:") {
+                Some(list) => {
+                    let order = list
+                        .split(',')
+                        .map(|ext| ext.trim().trim_start_matches('.').to_lowercase())
+                        .filter(|ext| !ext.is_empty())
+                        .collect();
+                    Ok(Self::Container(order))
+                }
+                None => Err(format!("invalid keep preference: {s:?}")),
+            },
+        }
+    }
+}
+
+impl fmt::Display for KeepPreference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Container(order) => write!(f, "container:{}", order.join(",")),
+            Self::Largest => write!(f, "largest"),
+            Self::Newest => write!(f, "newest"),
+        }
+    }
+}
+
+/// Groups entries by ID and returns groups with more than one file, in the
+/// order their IDs were first seen.
+///
+/// Within each group, files are sorted by `preferences`, with later
+/// preferences breaking ties of earlier ones, and the first file is marked
+/// to keep. Remaining ties keep the scan order.
+///
+/// Files that cannot be stat'd, for example because they were removed since
+/// the scan, are left out and reported as [`Error::UnreadableMetadata`],
+/// along with their group if it is left with a single file.
+pub fn find_duplicates<I>(
+    entries: I,
+    preferences: &[KeepPreference],
+) -> (Vec<DuplicateGroup>, Vec<Error>)
+where
+    I: IntoIterator<Item = MovieEntry>,
+{
+    let mut groups: Vec<(String, Vec<MovieEntry>)> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for entry in entries {
+        match index.get(&entry.id) {
+            Some(&i) => groups[i].1.push(entry),
+            None => {
+                index.insert(entry.id.clone(), groups.len());
+                groups.push((entry.id.clone(), vec![entry]));
+            }
+        }
+    }
+
+    let mut errors = Vec::new();
+    let groups = groups
+        .into_iter()
+        .filter(|(_, entries)| entries.len() > 1)
+        .filter_map(|(id, entries)| {
+            let mut files = Vec::with_capacity(entries.len());
+            for entry in entries {
+                match DuplicateFile::new(entry.path.clone(), entry.user) {
+                    Ok(file) => files.push(file),
+                    Err(source) => errors.push(Error::UnreadableMetadata {
+                        path: entry.path,
+                        source,
+                    }),
+                }
+            }
+            if files.len() < 2 {
+                return None;
+            }
+            files.sort_by(|a, b| {
+                preferences
+                    .iter()
+                    .map(|p| p.compare(a, b))
+                    .find(|o| o.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            });
+            files[0].keep = true;
+            Some(DuplicateGroup { id, files })
+        })
+        .collect();
+    (groups, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn file(extension: &str, size: u64, modified: i64) -> DuplicateFile {
+        DuplicateFile {
+            path: PathBuf::from(format!("@u/t [id].{extension}")),
+            user: "@u".to_owned(),
+            extension: extension.to_owned(),
+            size,
+            modified: Timestamp::from_secs(modified),
+            keep: false,
+        }
+    }
+
+    #[test]
+    fn preferences() {
+        let (mp4, webm) = (file("mp4", 100, 2), file("webm", 50, 1));
+        let container: KeepPreference = "container:webm,mkv".parse().unwrap();
+        assert_eq!(container.compare(&mp4, &webm), Ordering::Greater);
+        assert_eq!(KeepPreference::Largest.compare(&mp4, &webm), Ordering::Less);
+        assert_eq!(KeepPreference::Newest.compare(&mp4, &webm), Ordering::Less);
+    }
+
+    #[test]
+    fn missing_files_are_reported() {
+        let entry = |path: &Path| MovieEntry {
+            path: path.to_path_buf(),
+            id: "id".to_owned(),
+            user: "@u".to_owned(),
+            ..Default::default()
+        };
+        let dir = TempDir::new("dupes");
+        let (a, b) = (dir.join("a [id].mp4"), dir.join("b [id].webm"));
+        fs::write(&a, b"").unwrap();
+        fs::write(&b, b"").unwrap();
+        let missing = dir.join("c [id].mkv");
+        let entries = [entry(&a), entry(&missing), entry(&b)];
+
+        let (groups, errors) = find_duplicates(entries.clone(), &[KeepPreference::Largest]);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].files.len(), 2);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path(), missing);
+
+        fs::remove_file(&b).unwrap();
+        let (groups, errors) = find_duplicates(entries, &[KeepPreference::Largest]);
+        assert_eq!((groups.len(), errors.len()), (0, 2));
+    }
+
+    #[test]
+    fn preference_parsing() {
+        let actual = "container:.WebM, mp4".parse::<KeepPreference>();
+        let expected = KeepPreference::Container(vec!["webm".to_owned(), "mp4".to_owned()]);
+        assert_eq!(actual, Ok(expected));
+        assert!("smallest".parse::<KeepPreference>().is_err());
+    }
+}
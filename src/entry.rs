@@ -0,0 +1,669 @@
+use std::{
+    borrow::Cow,
+    mem,
+    ops::Range,
+    path::{Component, Path, PathBuf},
+    slice,
+    sync::LazyLock,
+};
+
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+use crate::{
+    date::{self, Date},
+    extensions, Container, Error, FileInfo, InfoJson, LegacyEncoding, Platform, ProbeInfo,
+    Template, UserRule, Verification,
+};
+
+/// Information on a movie file extracted from its path.
+///
+/// Fields other than `id`, `user` and `title` are only available when the
+/// movie has a yt-dlp `.info.json` sidecar; see
+/// [`MovieEntry::enrich_with_info_json`].
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct MovieEntry {
+    /// Path the entry was extracted from.
+    #[serde(skip)]
+    pub path: PathBuf,
+    pub id: String,
+    pub user: String,
+    pub title: String,
+    /// Title taken from the file name, only set by
+    /// [`MovieEntry::restore_title`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filename_title: Option<String>,
+    /// `title` and `user` as found in the path, only set when they are not
+    /// in NFC and [`ParseOptions::original_names`] is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_user: Option<String>,
+    /// Name of the [`Template`] the file stem matched.
+    pub template: String,
+    /// The [`UserRule`] that found `user`, as written in specifications.
+    pub user_rule: String,
+    pub platform: Platform,
+    /// Canonical URL of the video, if the platform is known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Date taken from `upload_date` or, failing that, found in the title;
+    /// see [`MovieEntry::detect_date`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date: Option<Date>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title_without_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uploader: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webpage_url: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Whether the path is not valid UTF-8, in which case `user` and `title`
+    /// have invalid bytes replaced with U+FFFD.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub non_utf8: bool,
+    /// Path with invalid bytes escaped as `\xNN` and backslashes doubled,
+    /// only for paths that are not valid UTF-8.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw_path: Option<String>,
+    /// Legacy encoding a path that is not valid UTF-8 was decoded from; see
+    /// [`ParseOptions::encodings`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<LegacyEncoding>,
+    /// Path decoded from `encoding`, which the file can be renamed to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub utf8_path: Option<String>,
+    /// Container recognized from the content of the file, only available
+    /// for files without a movie extension or when sniffing is enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container: Option<Container>,
+    /// Stream information, only available when probing is enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub probe: Option<ProbeInfo>,
+    /// Container integrity, only available when verification is enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub integrity: Option<Verification>,
+    /// File system information, only available when enabled.
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub file: Option<FileInfo>,
+}
+
+/// Options for extracting movie information from a path; see
+/// [`MovieEntry::from_path_with`].
+///
+/// The default parses the file stem with the bracket template and finds the
+/// user with the rule `prefix=@`, without normalizing names.
+#[derive(Debug, Clone, Copy)]
+pub struct ParseOptions<'a> {
+    /// Templates the file stem is parsed with, the first matching one winning.
+    pub templates: &'a [Template],
+    /// Encodings tried in order to decode paths that are not valid UTF-8.
+    pub encodings: &'a [LegacyEncoding],
+    /// Rules tried in order to find the user.
+    pub user_rules: &'a [UserRule],
+    /// Root of the scan, which [`UserRule::Depth`] counts from.
+    pub root: Option<&'a Path>,
+    /// Uploader in the `.info.json` sidecar, for [`UserRule::Uploader`].
+    pub uploader: Option<&'a str>,
+    /// Whether the file stem, directory names and user are normalized to
+    /// NFC, the form most systems other than macOS write file names in,
+    /// before templates and rules are matched against them.
+    pub normalize: bool,
+    /// Whether the title and user found in the path are kept in
+    /// `original_title` and `original_user` when normalizing changes them.
+    pub original_names: bool,
+}
+
+impl Default for ParseOptions<'_> {
+    fn default() -> Self {
+        static BRACKET: LazyLock<Template> = LazyLock::new(Template::bracket);
+        static PREFIX: LazyLock<UserRule> = LazyLock::new(UserRule::default);
+        Self {
+            templates: slice::from_ref(&*BRACKET),
+            encodings: &[],
+            user_rules: slice::from_ref(&*PREFIX),
+            root: None,
+            uploader: None,
+            normalize: false,
+            original_names: false,
+        }
+    }
+}
+
+impl MovieEntry {
+    /// Names of the fields in the serialized form, except those of
+    /// [`FileInfo`].
+    pub const FIELDS: [&'static str; 26] = [
+        "id",
+        "user",
+        "title",
+        "filename_title",
+        "original_title",
+        "original_user",
+        "template",
+        "user_rule",
+        "platform",
+        "url",
+        "date",
+        "title_without_date",
+        "uploader",
+        "channel_id",
+        "upload_date",
+        "duration",
+        "description",
+        "webpage_url",
+        "tags",
+        "non_utf8",
+        "raw_path",
+        "encoding",
+        "utf8_path",
+        "container",
+        "probe",
+        "integrity",
+    ];
+
+    /// Extracts movie information from a path such as
+    /// `@user/Title [id].webm`.
+    ///
+    /// Fails if the file stem has no trailing `[id]` or no directory in the
+    /// path starts with `@`. Paths that are not valid UTF-8 are parsed with
+    /// invalid bytes replaced, and the entry is flagged with `non_utf8`.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::from_path_with(path, &ParseOptions::default())
+    }
+
+    /// Same as [`MovieEntry::from_path`] but with the given options.
+    ///
+    /// The file stem is parsed with the first matching template, recorded
+    /// in `template`, and `user` is found with the first rule that finds
+    /// one, recorded in `user_rule`. [`UserRule::Depth`] counts from the
+    /// root if the path is under it, or else from the start of the path.
+    ///
+    /// With [`ParseOptions::normalize`], names are normalized to NFC before
+    /// matching, so that templates and rules written in NFC also match
+    /// names written in NFD on macOS.
+    ///
+    /// The components of paths that are not valid UTF-8 are first decoded
+    /// with the first encoding they are all valid in, recorded in
+    /// `encoding` along with the decoded path in `utf8_path`. Paths that
+    /// decode in none of them are parsed with invalid bytes replaced.
+    pub fn from_path_with<P: AsRef<Path>>(path: P, options: &ParseOptions) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let decoded = decode_path(path, options.encodings);
+        let name_path = decoded.as_ref().map_or(path, |(decoded, _)| decoded);
+        let stem = extensions::stem(name_path).unwrap_or_default();
+        let raw_stem = stem.to_string_lossy();
+        let stem = nfc(&raw_stem, options.normalize);
+        let (template, m) = options
+            .templates
+            .iter()
+            .find_map(|template| Some((template, template.captures(&stem)?)))
+            .ok_or_else(|| Error::NoIdBracket(path.to_path_buf()))?;
+        let raw_dirs = name_path
+            .parent()
+            .unwrap_or(Path::new(""))
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let dirs = raw_dirs
+            .iter()
+            .map(|dir| nfc(dir, options.normalize))
+            .collect::<Vec<_>>();
+        let below_root = options
+            .root
+            .and_then(|root| path.parent()?.strip_prefix(root).ok())
+            .map_or(dirs.len(), |relative| relative.components().count());
+        let (user, rule) = options
+            .user_rules
+            .iter()
+            .find_map(|rule| Some((rule.find(&dirs, below_root, options.uploader)?, rule)))
+            .ok_or_else(|| Error::NoUserComponent(path.to_path_buf()))?;
+        // The original forms are found where the normalized ones are in
+        // the names that normalizing changed.
+        let (user, mut original_user) = match nfc(&user, options.normalize) {
+            Cow::Owned(normalized) => (normalized, Some(user)),
+            Cow::Borrowed(_) => (user, None),
+        };
+        let mut original_title = None;
+        if options.original_names {
+            original_user = original_user.or_else(|| {
+                let (raw, dir, start) = raw_dirs
+                    .iter()
+                    .zip(&dirs)
+                    .rev()
+                    .filter(|(_, dir)| matches!(dir, Cow::Owned(_)))
+                    .find_map(|(raw, dir)| Some((raw, dir, dir.find(&user)?)))?;
+                let original = raw_span(raw, dir, start..start + user.len())?;
+                (original != user).then(|| original.to_owned())
+            });
+            if let Cow::Owned(_) = stem {
+                original_title = (m.title.as_ptr() as usize)
+                    .checked_sub(stem.as_ptr() as usize)
+                    .filter(|&start| stem.get(start..start + m.title.len()) == Some(m.title))
+                    .and_then(|start| raw_span(&raw_stem, &stem, start..start + m.title.len()))
+                    .filter(|&original| original != m.title);
+            }
+        }
+        let platform = Platform::detect(m.id);
+        let mut entry = MovieEntry {
+            path: path.to_path_buf(),
+            id: m.id.to_owned(),
+            title: m.title.to_owned(),
+            original_title: original_title.map(str::to_owned),
+            original_user: original_user.filter(|_| options.original_names),
+            user,
+            template: template.name().to_owned(),
+            user_rule: rule.to_string(),
+            platform,
+            url: platform.url(m.id),
+            upload_date: m.upload_date.map(str::to_owned),
+            non_utf8: path.to_str().is_none(),
+            raw_path: path
+                .to_str()
+                .is_none()
+                .then(|| escape_path(path).into_owned()),
+            encoding: decoded.as_ref().map(|&(_, encoding)| encoding),
+            utf8_path: decoded.map(|(decoded, _)| decoded.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+        entry.detect_date(false);
+        Ok(entry)
+    }
+
+    /// Sets `date` from `upload_date` if it is valid, or else from a date
+    /// found in the title.
+    ///
+    /// With `strip_title`, `title_without_date` is also set to the title with
+    /// the date removed when the title contains one.
+    pub fn detect_date(&mut self, strip_title: bool) {
+        let found = Date::find_in(&self.title);
+        let upload_date = self.upload_date.as_deref().and_then(Date::from_compact);
+        self.date = upload_date.or(found.as_ref().map(|(date, _)| *date));
+        self.title_without_date = match found {
+            Some((_, range)) if strip_title => Some(date::strip_date(&self.title, range)),
+            _ => None,
+        };
+    }
+
+    /// Replaces `title`, which comes from the file name, with the original
+    /// title of the video, keeping the former in `filename_title`.
+    ///
+    /// The title is `info_title`, typically from the `.info.json` sidecar,
+    /// if given. Otherwise the substitutions yt-dlp makes in file names are
+    /// reversed: look-alikes such as `⧸` and `：` become `/` and `:`, and if
+    /// the title looks like it was written with `--restrict-filenames`,
+    /// being ASCII with underscores and no spaces, `_-` becomes `:` and `_`
+    /// a space. Titles that really contain look-alikes are altered too.
+    pub fn restore_title(&mut self, info_title: Option<String>) {
+        let restored = info_title.unwrap_or_else(|| unsanitize(&self.title));
+        self.filename_title = Some(mem::replace(&mut self.title, restored));
+    }
+
+    /// Fills in fields from a yt-dlp sidecar.
+    ///
+    /// Fields missing from the sidecar are left untouched, so those derived
+    /// from the file name remain. If `webpage_url` points to a known
+    /// platform, it takes precedence over the platform guessed from the ID.
+    pub fn enrich_with_info_json(&mut self, info: InfoJson) {
+        let InfoJson {
+            title: _,
+            uploader,
+            channel_id,
+            upload_date,
+            duration,
+            description,
+            webpage_url,
+            tags,
+        } = info;
+        self.uploader = uploader.or(self.uploader.take());
+        self.channel_id = channel_id.or(self.channel_id.take());
+        self.upload_date = upload_date.or(self.upload_date.take());
+        self.duration = duration.or(self.duration);
+        self.description = description.or(self.description.take());
+        self.webpage_url = webpage_url.or(self.webpage_url.take());
+        if let Some(tags) = tags {
+            self.tags = tags;
+        }
+        if let Some(url) = &self.webpage_url {
+            let platform = Platform::from_url(url);
+            if platform != Platform::Unknown {
+                self.platform = platform;
+                self.url = platform.url(&self.id);
+            }
+        }
+    }
+}
+
+/// Reverses the substitutions yt-dlp makes in file names; see
+/// [`MovieEntry::restore_title`].
+fn unsanitize(title: &str) -> String {
+    let restricted = title.is_ascii() && title.contains('_') && !title.contains(' ');
+    if restricted {
+        return title.replace("_-", ":").replace('_', " ");
+    }
+    title
+        .chars()
+        .map(|c| match c {
+            '⧸' => '/',
+            '⧹' => '\\',
+            '＂' | '＊' | '：' | '＜' | '＞' | '？' | '｜' => {
+                char::from_u32(u32::from(c) - 0xFEE0).unwrap_or(c)
+            }
+            c => c,
+        })
+        .collect()
+}
+
+/// Returns `s` in NFC if `normalize` is set.
+fn nfc(s: &str, normalize: bool) -> Cow<'_, str> {
+    match normalize && !is_nfc(s) {
+        true => Cow::Owned(s.nfc().collect()),
+        false => Cow::Borrowed(s),
+    }
+}
+
+/// Returns the part of `raw` that `span` of its NFC form `normalized` comes
+/// from, or `None` if normalization crosses the ends of the span.
+fn raw_span<'a>(raw: &'a str, normalized: &str, span: Range<usize>) -> Option<&'a str> {
+    let offset = |at: usize| {
+        let (head, tail) = (normalized.get(..at)?, &normalized[at..]);
+        (0..=raw.len())
+            .filter(|&i| raw.is_char_boundary(i))
+            .find(|&i| raw[..i].nfc().eq(head.chars()) && raw[i..].nfc().eq(tail.chars()))
+    };
+    raw.get(offset(span.start)?..offset(span.end)?)
+}
+
+/// Decodes the components of a path that are not valid UTF-8 with the first
+/// of `encodings` they are all valid in.
+fn decode_path(path: &Path, encodings: &[LegacyEncoding]) -> Option<(PathBuf, LegacyEncoding)> {
+    if path.to_str().is_some() {
+        return None;
+    }
+    encodings.iter().find_map(|&encoding| {
+        let decoded = path
+            .components()
+            .map(|component| {
+                let s = component.as_os_str();
+                match s.to_str() {
+                    Some(s) => Some(s.to_owned()),
+                    None => encoding.decode(s.as_encoded_bytes()),
+                }
+            })
+            .collect::<Option<PathBuf>>()?;
+        Some((decoded, encoding))
+    })
+}
+
+/// Returns the path as is if it is valid UTF-8, or else with invalid bytes
+/// escaped as `\xNN` and backslashes doubled, so that it can be told apart
+/// from other paths with the same lossy form.
+pub(crate) fn escape_path(path: &Path) -> Cow<'_, str> {
+    if let Some(s) = path.to_str() {
+        return Cow::Borrowed(s);
+    }
+    let mut escaped = String::new();
+    for chunk in path.as_os_str().as_encoded_bytes().utf8_chunks() {
+        escaped.push_str(&chunk.valid().replace('\\', "\\\\"));
+        for byte in chunk.invalid() {
+            escaped.push_str(&format!("\\x{byte:02X}"));
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn movie_info_extraction() {
+        let path = Path::new("./@path/to/@foobar/baz/@FooBar (2024年11月1日) [aBcDeFgHiJkL].webm");
+        let actual = MovieEntry::from_path(path).ok();
+        let expected = Some(MovieEntry {
+            path: path.to_path_buf(),
+            id: "aBcDeFgHiJkL".to_owned(),
+            user: "@foobar".to_owned(),
+            title: "@FooBar (2024年11月1日)".to_owned(),
+            template: "bracket".to_owned(),
+            user_rule: "prefix=@".to_owned(),
+            date: Date::new(2024, 11, 1),
+            ..Default::default()
+        });
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn enrichment_with_info_json() {
+        let path = Path::new("@foobar/Title [aBcDeFgHiJkL].webm");
+        let mut entry = MovieEntry::from_path(path).unwrap();
+        entry.enrich_with_info_json(InfoJson {
+            uploader: Some("FooBar".to_owned()),
+            duration: Some(1.5),
+            webpage_url: Some("https://www.youtube.com/watch?v=aBcDeFgHiJkL".to_owned()),
+            ..Default::default()
+        });
+        let expected = MovieEntry {
+            path: path.to_path_buf(),
+            id: "aBcDeFgHiJkL".to_owned(),
+            user: "@foobar".to_owned(),
+            title: "Title".to_owned(),
+            template: "bracket".to_owned(),
+            user_rule: "prefix=@".to_owned(),
+            platform: Platform::YouTube,
+            url: Some("https://www.youtube.com/watch?v=aBcDeFgHiJkL".to_owned()),
+            uploader: Some("FooBar".to_owned()),
+            duration: Some(1.5),
+            webpage_url: Some("https://www.youtube.com/watch?v=aBcDeFgHiJkL".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(entry, expected);
+    }
+
+    #[test]
+    fn movie_info_extraction_with_templates() {
+        let templates = [
+            Template::bracket(),
+            Template::preset("date-title-id").unwrap(),
+        ];
+        let path = Path::new("@foobar/20241101_Foo_Bar_sm123.mp4");
+        let actual = MovieEntry::from_path_with(
+            path,
+            &ParseOptions {
+                templates: &templates,
+                ..Default::default()
+            },
+        )
+        .ok();
+        let expected = Some(MovieEntry {
+            path: path.to_path_buf(),
+            id: "sm123".to_owned(),
+            user: "@foobar".to_owned(),
+            title: "Foo_Bar".to_owned(),
+            template: "date-title-id".to_owned(),
+            user_rule: "prefix=@".to_owned(),
+            platform: Platform::Niconico,
+            url: Some("https://www.nicovideo.jp/watch/sm123".to_owned()),
+            date: Date::new(2024, 11, 1),
+            upload_date: Some("20241101".to_owned()),
+            ..Default::default()
+        });
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn title_restoration() {
+        let cases = [
+            (
+                "AC⧸DC： Live？ ＂Best＂ ｜ Part 1",
+                "AC/DC: Live? \"Best\" | Part 1",
+            ),
+            ("Foo_-_Bar_Baz", "Foo: Bar Baz"),
+            ("snake_case vs spaces", "snake_case vs spaces"),
+            ("日本語_タイトル", "日本語_タイトル"),
+        ];
+        for (title, expected) in cases {
+            assert_eq!(unsanitize(title), expected);
+        }
+
+        let mut entry = MovieEntry::from_path("@foo/Q： A [abc].mp4").unwrap();
+        entry.restore_title(Some("Q: A".to_owned()));
+        assert_eq!(entry.title, "Q: A");
+        assert_eq!(entry.filename_title.as_deref(), Some("Q： A"));
+    }
+
+    #[test]
+    fn normalization_and_full_width_brackets() {
+        // "ガイド" and "@がく" in NFD, as written by macOS.
+        let path =
+            "@\u{304B}\u{3099}\u{304F}/\u{30AB}\u{3099}イト\u{3099}\u{3000}［abc］\u{3000}.mp4";
+        let options = ParseOptions {
+            normalize: true,
+            original_names: true,
+            ..Default::default()
+        };
+        let entry = MovieEntry::from_path_with(path, &options).unwrap();
+        assert_eq!(entry.id, "abc");
+        assert_eq!(
+            (entry.user.as_str(), entry.title.as_str()),
+            ("@がく", "ガイド")
+        );
+        let original_title = entry.original_title.as_deref();
+        assert_eq!(original_title, Some("\u{30AB}\u{3099}イト\u{3099}"));
+        let original_user = entry.original_user.as_deref();
+        assert_eq!(original_user, Some("@\u{304B}\u{3099}\u{304F}"));
+
+        let entry = MovieEntry::from_path_with("@foo/Title [abc].mp4", &options).unwrap();
+        assert_eq!((entry.original_title, entry.original_user), (None, None));
+
+        // Templates and rules written in NFC match names written in NFD.
+        let templates = [Template::parse("t", r"^ガイド (?<id>\w+)$").unwrap()];
+        let user_rules = ["regex=^@(?<user>がく)$".parse().unwrap()];
+        let path = "@\u{304B}\u{3099}\u{304F}/\u{30AB}\u{3099}イト\u{3099} abc.mp4";
+        let options = ParseOptions {
+            templates: &templates,
+            user_rules: &user_rules,
+            ..options
+        };
+        let entry = MovieEntry::from_path_with(path, &options).unwrap();
+        assert_eq!((entry.id.as_str(), entry.user.as_str()), ("abc", "がく"));
+        assert_eq!(
+            entry.original_user.as_deref(),
+            Some("\u{304B}\u{3099}\u{304F}")
+        );
+        let unnormalized = ParseOptions {
+            normalize: false,
+            ..options
+        };
+        assert!(MovieEntry::from_path_with(path, &unnormalized).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_paths() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let path = Path::new(OsStr::from_bytes(b"@foo\xFF/T\\itle \x82\xA0 [abc].mp4"));
+        let entry = MovieEntry::from_path(path).unwrap();
+        assert_eq!(entry.id, "abc");
+        assert_eq!(entry.user, "@foo\u{FFFD}");
+        assert_eq!(entry.title, "T\\itle \u{FFFD}\u{FFFD}");
+        assert!(entry.non_utf8);
+        let raw_path = entry.raw_path.as_deref();
+        assert_eq!(raw_path, Some(r"@foo\xFF/T\\itle \x82\xA0 [abc].mp4"));
+
+        let entry = MovieEntry::from_path("@foo/Title [abc].mp4").unwrap();
+        assert!(!entry.non_utf8);
+        assert_eq!(entry.raw_path, None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn legacy_encodings() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        // "@ユーザー/動画 [abc].mp4" in CP932.
+        let bytes = b"/m/@\x83\x86\x81[\x83U\x81[/\x93\xae\x89\xe6 [abc].mp4";
+        let path = Path::new(OsStr::from_bytes(bytes));
+        let encodings = [LegacyEncoding::EucJp, LegacyEncoding::Cp932];
+        let options = ParseOptions {
+            encodings: &encodings,
+            ..Default::default()
+        };
+        let entry = MovieEntry::from_path_with(path, &options).unwrap();
+        assert_eq!(
+            (entry.user.as_str(), entry.title.as_str()),
+            ("@ユーザー", "動画")
+        );
+        assert_eq!(entry.encoding, Some(LegacyEncoding::Cp932));
+        assert_eq!(
+            entry.utf8_path.as_deref(),
+            Some("/m/@ユーザー/動画 [abc].mp4")
+        );
+        assert!(entry.non_utf8);
+    }
+
+    #[test]
+    fn user_rules() {
+        let rules = [
+            "prefix=@",
+            r"regex=^UC[\w-]+$",
+            "uploader",
+            "depth=1",
+            "literal=unknown",
+        ]
+        .map(|spec| spec.parse::<UserRule>().unwrap());
+        let user = |path: &str, uploader| {
+            let options = ParseOptions {
+                user_rules: &rules,
+                root: Some(Path::new("/archive")),
+                uploader,
+                ..Default::default()
+            };
+            let entry = MovieEntry::from_path_with(path, &options).unwrap();
+            (entry.user, entry.user_rule)
+        };
+        let cases = [
+            ("/archive/@foo/sub/t [a].mp4", None, "@foo", "prefix=@"),
+            (
+                "/archive/x/UCabc-1/t [a].mp4",
+                None,
+                "UCabc-1",
+                r"regex=^UC[\w-]+$",
+            ),
+            (
+                "/archive/Foo Ch/sub/t [a].mp4",
+                Some("Foo"),
+                "Foo",
+                "uploader",
+            ),
+            ("/archive/Foo Ch/sub/t [a].mp4", None, "Foo Ch", "depth=1"),
+            ("/archive/t [a].mp4", None, "unknown", "literal=unknown"),
+        ];
+        for (path, uploader, expected_user, expected_rule) in cases {
+            let expected = (expected_user.to_owned(), expected_rule.to_owned());
+            assert_eq!(user(path, uploader), expected, "{path}");
+        }
+    }
+
+    #[test]
+    fn movie_info_extraction_errors() {
+        let actual = MovieEntry::from_path("@foobar/no bracket.webm");
+        assert!(matches!(actual, Err(Error::NoIdBracket(_))));
+
+        let actual = MovieEntry::from_path("foobar/title [id].webm");
+        assert!(matches!(actual, Err(Error::NoUserComponent(_))));
+    }
+}
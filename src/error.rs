@@ -0,0 +1,161 @@
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+/// Reasons why a path found during a scan did not produce a
+/// [`MovieEntry`](crate::MovieEntry).
+#[derive(Debug)]
+pub enum Error {
+    /// A directory could not be read.
+    UnreadableDir { path: PathBuf, source: io::Error },
+    /// The target of a symbolic link does not exist or cannot be read.
+    DanglingSymlink { path: PathBuf, source: io::Error },
+    /// A directory is reached again through a symbolic link below it.
+    SymlinkLoop(PathBuf),
+    /// The file stem does not end with an `[id]` bracket, or more generally
+    /// matches none of the filename templates.
+    NoIdBracket(PathBuf),
+    /// No [`UserRule`](crate::UserRule) finds a user, which by default means
+    /// that no directory in the path starts with `@`.
+    NoUserComponent(PathBuf),
+    /// The file does not have one of the movie extensions, or has none, and
+    /// its content is not recognized as a movie.
+    UnsupportedExtension(PathBuf),
+    /// The file has one of the movie extensions but its content is not
+    /// recognized as a movie.
+    UnrecognizedContent(PathBuf),
+    /// The `.info.json` sidecar of a movie could not be read or parsed.
+    InvalidInfoJson { path: PathBuf, source: io::Error },
+    /// A `.lsmovieignore` file could not be read or has a malformed pattern.
+    InvalidIgnoreFile { path: PathBuf, source: io::Error },
+    /// File system information on a movie file could not be read.
+    UnreadableMetadata { path: PathBuf, source: io::Error },
+    /// The container of a movie file could not be probed or verified.
+    ProbeFailed { path: PathBuf, source: io::Error },
+    /// A directory could not be watched for changes.
+    WatchFailed { path: PathBuf, source: io::Error },
+    /// A scan cache file is malformed and was ignored, so nothing was
+    /// reused from it.
+    InvalidCache { path: PathBuf, source: io::Error },
+}
+
+impl Error {
+    /// Returns a stable, machine-readable name of the error kind.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::UnreadableDir { .. } => "unreadable_dir",
+            Self::DanglingSymlink { .. } => "dangling_symlink",
+            Self::SymlinkLoop(_) => "symlink_loop",
+            Self::NoIdBracket(_) => "no_id_bracket",
+            Self::NoUserComponent(_) => "no_user_component",
+            Self::UnsupportedExtension(_) => "unsupported_extension",
+            Self::UnrecognizedContent(_) => "unrecognized_content",
+            Self::InvalidInfoJson { .. } => "invalid_info_json",
+            Self::InvalidIgnoreFile { .. } => "invalid_ignore_file",
+            Self::UnreadableMetadata { .. } => "unreadable_metadata",
+            Self::ProbeFailed { .. } => "probe_failed",
+            Self::WatchFailed { .. } => "watch_failed",
+            Self::InvalidCache { .. } => "invalid_cache",
+        }
+    }
+
+    /// Returns the path the error is about.
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::UnreadableDir { path, .. }
+            | Self::DanglingSymlink { path, .. }
+            | Self::SymlinkLoop(path)
+            | Self::NoIdBracket(path)
+            | Self::NoUserComponent(path)
+            | Self::UnsupportedExtension(path)
+            | Self::UnrecognizedContent(path)
+            | Self::InvalidInfoJson { path, .. }
+            | Self::InvalidIgnoreFile { path, .. }
+            | Self::UnreadableMetadata { path, .. }
+            | Self::ProbeFailed { path, .. }
+            | Self::WatchFailed { path, .. }
+            | Self::InvalidCache { path, .. } => path,
+        }
+    }
+
+    /// Returns `true` if the error indicates a real failure rather than a
+    /// file that is simply not a movie or a cache that was ignored without
+    /// affecting the results.
+    pub fn is_failure(&self) -> bool {
+        !matches!(
+            self,
+            Self::UnsupportedExtension(_) | Self::InvalidCache { .. }
+        )
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnreadableDir { path, source } => {
+                write!(f, "directory could not be read: {path:?}: {source}")
+            }
+            Self::DanglingSymlink { path, source } => {
+                write!(
+                    f,
+                    "symbolic link target could not be read: {path:?}: {source}"
+                )
+            }
+            Self::SymlinkLoop(path) => write!(f, "symbolic link loop: {path:?}"),
+            Self::NoIdBracket(path) => write!(f, "file name matches no template: {path:?}"),
+            Self::NoUserComponent(path) => write!(f, "no user found in path: {path:?}"),
+            Self::UnsupportedExtension(path) => write!(f, "unsupported extension: {path:?}"),
+            Self::UnrecognizedContent(path) => {
+                write!(f, "content is not a recognized movie container: {path:?}")
+            }
+            Self::InvalidInfoJson { path, source } => {
+                write!(f, "info.json sidecar could not be read: {path:?}: {source}")
+            }
+            Self::InvalidIgnoreFile { path, source } => {
+                write!(f, "ignore file could not be read: {path:?}: {source}")
+            }
+            Self::UnreadableMetadata { path, source } => {
+                write!(f, "file metadata could not be read: {path:?}: {source}")
+            }
+            Self::ProbeFailed { path, source } => {
+                write!(f, "container could not be probed: {path:?}: {source}")
+            }
+            Self::WatchFailed { path, source } => {
+                write!(f, "directory could not be watched: {path:?}: {source}")
+            }
+            Self::InvalidCache { path, source } => {
+                write!(f, "ignoring broken cache: {path:?}: {source}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::UnreadableDir { source, .. }
+            | Self::DanglingSymlink { source, .. }
+            | Self::InvalidInfoJson { source, .. }
+            | Self::InvalidIgnoreFile { source, .. }
+            | Self::UnreadableMetadata { source, .. }
+            | Self::ProbeFailed { source, .. }
+            | Self::WatchFailed { source, .. }
+            | Self::InvalidCache { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Serializes the error as a record of `kind`, `path` and `message`.
+impl serde::Serialize for Error {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut record = serializer.serialize_struct("Error", 3)?;
+        record.serialize_field("kind", self.kind())?;
+        record.serialize_field("path", &self.path().to_string_lossy())?;
+        record.serialize_field("message", &self.to_string())?;
+        record.end()
+    }
+}
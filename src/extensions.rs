@@ -0,0 +1,157 @@
+use std::{ffi::OsStr, fmt, path::Path, str::FromStr};
+
+/// Longest extension taken to be one by [`extension`].
+const MAX_EXTENSION_LEN: usize = 8;
+
+/// Named sets of extensions usable in place of extensions in an
+/// [`Extensions`] list.
+const PRESETS: [(&str, &[&str]); 2] = [
+    (
+        "video",
+        &["mkv", "mp4", "webm", "m4v", "mov", "flv", "ts", "avi"],
+    ),
+    ("audio", &["m4a", "opus", "mp3"]),
+];
+
+/// Extensions of the files to list, matched case-insensitively. Defaults to
+/// the `video` preset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extensions(Vec<String>);
+
+impl Extensions {
+    /// Returns the extensions of a preset: `video` (mkv, mp4, webm, m4v,
+    /// mov, flv, ts and avi) or `audio` (m4a, opus and mp3).
+    pub fn preset(name: &str) -> Option<Self> {
+        let (_, extensions) = PRESETS.iter().find(|(preset, _)| *preset == name)?;
+        Some(Self(extensions.iter().map(|&ext| ext.to_owned()).collect()))
+    }
+
+    /// Creates a list from items that are either preset names or
+    /// extensions, with or without a leading dot.
+    pub fn from_items<S: AsRef<str>>(items: &[S]) -> Result<Self, String> {
+        let mut extensions = Vec::<String>::new();
+        for item in items {
+            let item = item.as_ref().trim();
+            let added = match Self::preset(item) {
+                Some(preset) => preset.0,
+                None => {
+                    let ext = item.strip_prefix('.').unwrap_or(item);
+                    if ext.is_empty() || ext.contains(['.', '/', '\\']) {
+                        return Err(format!("invalid extension: {item:?}"));
+                    }
+                    vec![ext.to_lowercase()]
+                }
+            };
+            for ext in added {
+                if !extensions.contains(&ext) {
+                    extensions.push(ext);
+                }
+            }
+        }
+        match extensions.is_empty() {
+            true => Err("empty extension list".to_owned()),
+            false => Ok(Self(extensions)),
+        }
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(String::as_str)
+    }
+
+    /// Returns `true` if the extension is in the list, ignoring case.
+    pub fn contains(&self, ext: &OsStr) -> bool {
+        let ext = ext.as_encoded_bytes();
+        self.0
+            .iter()
+            .any(|e| e.as_bytes().eq_ignore_ascii_case(ext))
+    }
+
+    /// Returns `true` if the path has one of the extensions.
+    pub fn matches(&self, path: &Path) -> bool {
+        extension(path).is_some_and(|ext| self.contains(ext))
+    }
+}
+
+/// Returns the extension of a path, or `None` if what follows the last dot
+/// does not look like an extension, being longer than a few characters or
+/// not ASCII alphanumeric, as in `Version 1.5 release [id]`.
+pub(crate) fn extension(path: &Path) -> Option<&OsStr> {
+    path.extension().filter(|ext| {
+        let ext = ext.as_encoded_bytes();
+        !ext.is_empty()
+            && ext.len() <= MAX_EXTENSION_LEN
+            && ext.iter().all(u8::is_ascii_alphanumeric)
+    })
+}
+
+/// Returns the file name of a path without the extension returned by
+/// [`extension`], if any.
+pub(crate) fn stem(path: &Path) -> Option<&OsStr> {
+    match extension(path) {
+        Some(_) => path.file_stem(),
+        None => path.file_name(),
+    }
+}
+
+impl Default for Extensions {
+    fn default() -> Self {
+        Self::preset("video").unwrap()
+    }
+}
+
+/// Parses a comma-separated list of preset names and extensions, such as
+/// `video,audio,.wmv`.
+impl FromStr for Extensions {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let items = s.split(',').filter(|item| !item.trim().is_empty());
+        Self::from_items(&items.collect::<Vec<_>>())
+    }
+}
+
+impl fmt::Display for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{probe::sample_mp4, testing::TempDir, Scanner};
+
+    #[test]
+    fn presets_and_case_insensitive_matching() {
+        let extensions: Extensions = "audio, .MKV,mp3".parse().unwrap();
+        assert_eq!(extensions.to_string(), "m4a,opus,mp3,mkv");
+        assert!(extensions.matches(Path::new("@u/Title [a].Opus")));
+        assert!(extensions.matches(Path::new("@u/Title [a].mkv")));
+        assert!(!extensions.matches(Path::new("@u/Title [a].mp4")));
+        assert!(!extensions.matches(Path::new("@u/Title [a]")));
+
+        assert!(Extensions::default().matches(Path::new("@u/Title [a].MP4")));
+        assert!(Extensions::default().matches(Path::new("@u/Title [a].ts")));
+        assert!("mp4,tar.gz".parse::<Extensions>().is_err());
+        assert!(" , ".parse::<Extensions>().is_err());
+    }
+
+    #[test]
+    fn dotted_names_without_extension() {
+        let name = Path::new("Version 1.5 release [bbcdefghijk]");
+        assert_eq!(extension(name), None);
+        assert_eq!(stem(name), Some(name.as_os_str()));
+        let name = Path::new("Version 1.5 release [bbcdefghijk].MP4");
+        assert_eq!(extension(name), Some(OsStr::new("MP4")));
+
+        let dir = TempDir::new("dotted");
+        let path = dir.join("@u").join("Version 1.5 release [bbcdefghijk]");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, sample_mp4()).unwrap();
+        let entry = Scanner::new().process(&path).unwrap();
+        assert_eq!(entry.title, "Version 1.5 release");
+        assert_eq!(entry.id, "bbcdefghijk");
+    }
+}
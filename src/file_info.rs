@@ -0,0 +1,126 @@
+use std::{
+    fs, io,
+    path::{self, Path, PathBuf},
+};
+
+use crate::{extensions, Timestamp};
+
+/// File system information on a movie file.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct FileInfo {
+    /// Absolute path, without resolving symbolic links.
+    #[serde(rename = "path", serialize_with = "serialize_path")]
+    pub absolute_path: PathBuf,
+    /// Path relative to the root directory of the scan.
+    #[serde(serialize_with = "serialize_opt_path")]
+    pub relative_path: Option<PathBuf>,
+    /// Extension in lowercase, or an empty string if there is none.
+    pub extension: String,
+    pub size: u64,
+    pub mtime: Option<Timestamp>,
+    /// Status change time on Unix and creation time elsewhere.
+    pub ctime: Option<Timestamp>,
+    pub inode: Option<u64>,
+    pub device: Option<u64>,
+}
+
+impl FileInfo {
+    /// Names of the fields in the serialized form.
+    pub const FIELDS: [&'static str; 8] = [
+        "path",
+        "relative_path",
+        "extension",
+        "size",
+        "mtime",
+        "ctime",
+        "inode",
+        "device",
+    ];
+
+    /// Reads information on a file, following symbolic links.
+    ///
+    /// `root` is the directory `relative_path` is computed against.
+    pub fn from_path<P: AsRef<Path>>(path: P, root: Option<&Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let metadata = fs::metadata(path)?;
+        Self::from_metadata(path, root, &metadata)
+    }
+
+    pub fn from_metadata(
+        path: &Path,
+        root: Option<&Path>,
+        metadata: &fs::Metadata,
+    ) -> io::Result<Self> {
+        let relative_path = root
+            .and_then(|root| path.strip_prefix(root).ok())
+            .map(Path::to_path_buf);
+        let extension = extensions::extension(path).unwrap_or_default();
+        let (ctime, inode, device) = unix_fields(metadata);
+        Ok(Self {
+            absolute_path: path::absolute(path)?,
+            relative_path,
+            extension: extension.to_string_lossy().to_lowercase(),
+            size: metadata.len(),
+            mtime: metadata.modified().ok().map(Timestamp::from),
+            ctime,
+            inode,
+            device,
+        })
+    }
+}
+
+#[cfg(unix)]
+fn unix_fields(metadata: &fs::Metadata) -> (Option<Timestamp>, Option<u64>, Option<u64>) {
+    use std::os::unix::fs::MetadataExt;
+
+    let ctime = Timestamp::from_secs(metadata.ctime());
+    (Some(ctime), Some(metadata.ino()), Some(metadata.dev()))
+}
+
+#[cfg(not(unix))]
+fn unix_fields(metadata: &fs::Metadata) -> (Option<Timestamp>, Option<u64>, Option<u64>) {
+    (metadata.created().ok().map(Timestamp::from), None, None)
+}
+
+pub(crate) fn serialize_path<S: serde::Serializer>(
+    path: &Path,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&path.to_string_lossy())
+}
+
+fn serialize_opt_path<S: serde::Serializer>(
+    path: &Option<PathBuf>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match path {
+        Some(path) => serialize_path(path, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn relative_path_and_extension() {
+        let root = TempDir::new("file-info");
+        let dir = root.join("@user");
+        let path = dir.join("Title [abc].MP4");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, b"data").unwrap();
+
+        let info = FileInfo::from_path(&path, Some(&root)).unwrap();
+        assert_eq!(info.absolute_path, path::absolute(&path).unwrap());
+        let relative_path = info.relative_path.as_deref();
+        assert_eq!(relative_path, Some(Path::new("@user/Title [abc].MP4")));
+        assert_eq!(info.extension, "mp4");
+        assert_eq!(info.size, 4);
+        assert!(info.mtime.is_some());
+
+        let info = FileInfo::from_path(&path, Some(Path::new("/elsewhere"))).unwrap();
+        assert_eq!(info.relative_path, None);
+    }
+}
@@ -0,0 +1,181 @@
+//! Selection of the files and directories to scan.
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    Match,
+};
+
+use crate::Error;
+
+/// Name of the files listing patterns of files and directories to skip, with
+/// the syntax and semantics of `.gitignore` files.
+pub const IGNORE_FILE: &str = ".lsmovieignore";
+
+/// Patterns selecting files and directories, with the syntax of
+/// `.gitignore` files, matched against paths relative to the root of the
+/// scan.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    exclude: Option<Gitignore>,
+    include: Option<Gitignore>,
+}
+
+impl Filter {
+    /// Creates a filter skipping files and directories that match any of
+    /// the `exclude` patterns and, unless `include` is empty, files that
+    /// neither match any of the `include` patterns nor are in a directory
+    /// that does. Excluding takes precedence over including.
+    pub fn new<S: AsRef<str>>(exclude: &[S], include: &[S]) -> Result<Self, InvalidPattern> {
+        Ok(Self {
+            exclude: matcher(exclude)?,
+            include: matcher(include)?,
+        })
+    }
+
+    /// Returns `true` if the filter selects the path, relative to the root.
+    pub fn selects(&self, relative: &Path, is_dir: bool) -> bool {
+        if let Some(exclude) = &self.exclude {
+            if exclude.matched(relative, is_dir).is_ignore() {
+                return false;
+            }
+        }
+        match &self.include {
+            Some(include) if !is_dir => include
+                .matched_path_or_any_parents(relative, is_dir)
+                .is_ignore(),
+            _ => true,
+        }
+    }
+}
+
+fn matcher<S: AsRef<str>>(patterns: &[S]) -> Result<Option<Gitignore>, InvalidPattern> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GitignoreBuilder::new("");
+    for pattern in patterns {
+        let pattern = pattern.as_ref();
+        let invalid = |e: ignore::Error| InvalidPattern {
+            pattern: pattern.to_owned(),
+            message: e.to_string(),
+        };
+        builder.add_line(None, pattern).map_err(invalid)?;
+    }
+    let matcher = builder.build().map_err(|e| InvalidPattern {
+        pattern: String::new(),
+        message: e.to_string(),
+    })?;
+    Ok(Some(matcher))
+}
+
+/// Error returned by [`Filter::new`] for a malformed pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidPattern {
+    pub pattern: String,
+    message: String,
+}
+
+impl fmt::Display for InvalidPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid pattern {:?}: {}", self.pattern, self.message)
+    }
+}
+
+impl std::error::Error for InvalidPattern {}
+
+/// [`IGNORE_FILE`]s of the directories a scan is in, innermost first.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Ignores(Option<Arc<IgnoreFile>>);
+
+#[derive(Debug)]
+struct IgnoreFile {
+    matcher: Gitignore,
+    parent: Ignores,
+}
+
+impl Ignores {
+    /// Returns the ignore files in effect in `dir`, a subdirectory of the
+    /// directory of `self` or a root, reading the one in `dir` if any.
+    pub(crate) fn enter(&self, dir: &Path) -> Result<Self, Error> {
+        let path = dir.join(IGNORE_FILE);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(self.clone()),
+            Err(source) => return Err(Error::InvalidIgnoreFile { path, source }),
+        };
+        let invalid = |path: &PathBuf, e: ignore::Error| Error::InvalidIgnoreFile {
+            path: path.clone(),
+            source: io::Error::new(io::ErrorKind::InvalidData, e),
+        };
+        let mut builder = GitignoreBuilder::new(dir);
+        for line in contents.lines() {
+            builder
+                .add_line(Some(path.clone()), line)
+                .map_err(|e| invalid(&path, e))?;
+        }
+        let matcher = builder.build().map_err(|e| invalid(&path, e))?;
+        let parent = self.clone();
+        Ok(Self(Some(Arc::new(IgnoreFile { matcher, parent }))))
+    }
+
+    /// Returns `true` if the innermost ignore file with a pattern matching
+    /// the path ignores it rather than negating an outer pattern.
+    pub(crate) fn ignores(&self, path: &Path, is_dir: bool) -> bool {
+        let mut file = self.0.as_deref();
+        while let Some(IgnoreFile { matcher, parent }) = file {
+            match matcher.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => file = parent.0.as_deref(),
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn patterns() {
+        let filter = Filter::new(&["_incoming", "*.part.mp4"], &["@alice/", "*.mkv"]).unwrap();
+        let cases = [
+            ("_incoming", true, false),
+            ("@bob/_incoming", true, false),
+            ("@bob", true, true),
+            ("@bob/Title [a].mp4", false, false),
+            ("@bob/Title [a].mkv", false, true),
+            ("@alice/sub/Title [a].mp4", false, true),
+            ("@alice/Title [a].part.mp4", false, false),
+        ];
+        for (path, is_dir, expected) in cases {
+            assert_eq!(filter.selects(Path::new(path), is_dir), expected, "{path}");
+        }
+        assert!(Filter::new(&["{a,b", "c"], &[]).is_err());
+    }
+
+    #[test]
+    fn nested_ignore_files() {
+        let root = TempDir::new("ignore");
+        let dir = root.join("@u");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(root.join(IGNORE_FILE), "*.webm\n.trash/\n").unwrap();
+        fs::write(dir.join(IGNORE_FILE), "!keep*.webm\n").unwrap();
+
+        let outer = Ignores::default().enter(&root).unwrap();
+        let inner = outer.enter(&dir).unwrap();
+        assert!(inner.ignores(&dir.join("Title [a].webm"), false));
+        assert!(!inner.ignores(&dir.join("keep [a].webm"), false));
+        assert!(!inner.ignores(&dir.join("Title [a].mp4"), false));
+        assert!(inner.ignores(&dir.join(".trash"), true));
+        assert!(!inner.ignores(&dir.join(".trash"), false));
+    }
+}
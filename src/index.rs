@@ -0,0 +1,452 @@
+use std::{
+    fmt, io,
+    path::{self, Path, PathBuf},
+    time::SystemTime,
+};
+
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde_json::Value;
+
+use crate::{entry::escape_path, FileInfo, InfoJson, MovieEntry, Timestamp};
+
+const SCHEMA_VERSION: i64 = 1;
+
+/// Fields of file information that change without the movie changing, such
+/// as when scanning from another root or touching the file, and so are left
+/// out when telling whether an entry was updated.
+const VOLATILE_FIELDS: [&str; 5] = ["relative_path", "mtime", "ctime", "inode", "device"];
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS scans (
+    id INTEGER PRIMARY KEY,
+    started_at INTEGER NOT NULL,
+    finished_at INTEGER
+);
+CREATE TABLE IF NOT EXISTS movies (
+    path TEXT PRIMARY KEY,
+    id TEXT NOT NULL,
+    user TEXT NOT NULL,
+    title TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    mtime INTEGER,
+    entry TEXT NOT NULL,
+    first_seen INTEGER NOT NULL,
+    last_scan INTEGER NOT NULL REFERENCES scans (id),
+    deleted_at INTEGER
+);
+CREATE INDEX IF NOT EXISTS movies_id ON movies (id);
+CREATE INDEX IF NOT EXISTS movies_user ON movies (user);
+";
+
+/// Persistent SQLite index of scanned movie files.
+///
+/// Entries are keyed by absolute path. Files that are not seen again when
+/// their directory is rescanned are kept but marked as deleted.
+#[derive(Debug)]
+pub struct Index {
+    conn: Connection,
+}
+
+impl Index {
+    /// Opens an index, creating the database if it does not exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, IndexError> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    /// Opens a temporary index in memory.
+    pub fn open_in_memory() -> Result<Self, IndexError> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(conn: Connection) -> Result<Self, IndexError> {
+        let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(IndexError::UnsupportedVersion(version));
+        }
+        conn.execute_batch(SCHEMA)?;
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        Ok(Self { conn })
+    }
+
+    /// Starts recording the results of a scan of `roots`.
+    pub fn update<P: AsRef<Path>>(&mut self, roots: &[P]) -> Result<IndexUpdate<'_>, IndexError> {
+        let roots = roots
+            .iter()
+            .map(|root| path::absolute(root.as_ref()))
+            .collect::<io::Result<_>>()?;
+        let tx = self.conn.transaction()?;
+        let now = Timestamp::from(SystemTime::now()).secs;
+        tx.execute("INSERT INTO scans (started_at) VALUES (?1)", [now])?;
+        let scan = tx.last_insert_rowid();
+        Ok(IndexUpdate {
+            tx,
+            scan,
+            now,
+            roots,
+            skipped_dirs: Vec::new(),
+            skipped_paths: Vec::new(),
+            stats: IndexStats::default(),
+        })
+    }
+
+    /// Returns indexed entries matching a query, ordered by path.
+    pub fn query(&self, query: &Query) -> Result<Vec<IndexedEntry>, IndexError> {
+        let mut sql = String::from("SELECT entry, deleted_at FROM movies WHERE 1 = 1");
+        match query.deleted {
+            Deleted::Exclude => sql.push_str(" AND deleted_at IS NULL"),
+            Deleted::Include => {}
+            Deleted::Only => sql.push_str(" AND deleted_at IS NOT NULL"),
+        }
+        if query.id.is_some() {
+            sql.push_str(" AND id = :id");
+        }
+        if query.user.is_some() {
+            sql.push_str(" AND user = :user");
+        }
+        sql.push_str(" ORDER BY path");
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let mut params: Vec<(&str, &dyn rusqlite::ToSql)> = Vec::new();
+        if let Some(id) = &query.id {
+            params.push((":id", id));
+        }
+        if let Some(user) = &query.user {
+            params.push((":user", user));
+        }
+        let rows = stmt.query_map(params.as_slice(), |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, Option<i64>>(1)?))
+        })?;
+        rows.map(|row| {
+            let (entry, deleted_at) = row?;
+            Ok(IndexedEntry {
+                entry: serde_json::from_str(&entry)?,
+                deleted_at: deleted_at.map(Timestamp::from_secs),
+            })
+        })
+        .collect()
+    }
+}
+
+/// Recording of the results of one scan into an [`Index`], created by
+/// [`Index::update`].
+///
+/// Nothing is written to the database unless [`IndexUpdate::finish`] is
+/// called.
+#[derive(Debug)]
+pub struct IndexUpdate<'a> {
+    tx: Transaction<'a>,
+    scan: i64,
+    now: i64,
+    roots: Vec<PathBuf>,
+    skipped_dirs: Vec<PathBuf>,
+    skipped_paths: Vec<PathBuf>,
+    stats: IndexStats,
+}
+
+impl IndexUpdate<'_> {
+    /// Inserts or updates an entry.
+    ///
+    /// File information is read from the file system if the entry has none.
+    pub fn upsert(&mut self, entry: &MovieEntry) -> Result<(), IndexError> {
+        let file = match &entry.file {
+            Some(file) => file.clone(),
+            None => FileInfo::from_path(&entry.path, None)?,
+        };
+        let mut value = serde_json::to_value(entry)?;
+        if entry.file.is_none() {
+            if let (Value::Object(map), Value::Object(file)) =
+                (&mut value, serde_json::to_value(&file)?)
+            {
+                map.extend(file);
+            }
+        }
+        let path = escape_path(&file.absolute_path);
+        let entry_json = value.to_string();
+        let previous = self
+            .tx
+            .query_row(
+                "SELECT entry, deleted_at IS NOT NULL FROM movies WHERE path = ?1",
+                [&*path],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, bool>(1)?)),
+            )
+            .optional()?;
+        self.tx.execute(
+            "INSERT INTO movies
+                (path, id, user, title, size, mtime, entry, first_seen, last_scan)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+             ON CONFLICT (path) DO UPDATE SET
+                id = excluded.id,
+                user = excluded.user,
+                title = excluded.title,
+                size = excluded.size,
+                mtime = excluded.mtime,
+                entry = excluded.entry,
+                last_scan = excluded.last_scan,
+                deleted_at = NULL",
+            params![
+                path,
+                entry.id,
+                entry.user,
+                entry.title,
+                file.size as i64,
+                file.mtime.map(|t| t.secs),
+                entry_json,
+                self.now,
+                self.scan,
+            ],
+        )?;
+        match previous {
+            None | Some((_, true)) => self.stats.added += 1,
+            Some((previous, false)) => {
+                if content(serde_json::from_str(&previous)?) != content(value) {
+                    self.stats.updated += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Excludes files under a directory from being marked as deleted, for
+    /// directories that could not be read during the scan.
+    pub fn skip_dir<P: AsRef<Path>>(&mut self, dir: P) -> Result<(), IndexError> {
+        self.skipped_dirs.push(path::absolute(dir.as_ref())?);
+        Ok(())
+    }
+
+    /// Excludes a file from being marked as deleted, for files that could
+    /// not be processed during the scan. For the path of an `.info.json`
+    /// sidecar, the movie it belongs to is excluded.
+    pub fn skip_path<P: AsRef<Path>>(&mut self, path: P) -> Result<(), IndexError> {
+        self.skipped_paths.push(path::absolute(path.as_ref())?);
+        Ok(())
+    }
+
+    /// Marks entries under the scanned roots that were not seen as deleted
+    /// and commits the update.
+    pub fn finish(self) -> Result<IndexStats, IndexError> {
+        let Self {
+            tx,
+            scan,
+            now,
+            roots,
+            skipped_dirs,
+            skipped_paths,
+            mut stats,
+        } = self;
+        let vanished = {
+            let mut stmt =
+                tx.prepare("SELECT path FROM movies WHERE deleted_at IS NULL AND last_scan != ?1")?;
+            let paths = stmt.query_map([scan], |row| row.get::<_, String>(0))?;
+            paths.collect::<Result<Vec<_>, _>>()?
+        };
+        for path in vanished {
+            let path_ref = Path::new(&path);
+            let under = |dir: &PathBuf| path_ref.starts_with(dir);
+            let sidecar = InfoJson::sidecar_path(path_ref);
+            let skipped = skipped_dirs.iter().any(under)
+                || skipped_paths
+                    .iter()
+                    .any(|skipped| skipped == path_ref || *skipped == sidecar);
+            if roots.iter().any(under) && !skipped {
+                tx.execute(
+                    "UPDATE movies SET deleted_at = ?1 WHERE path = ?2",
+                    params![now, path],
+                )?;
+                stats.deleted += 1;
+            }
+        }
+        let finished_at = Timestamp::from(SystemTime::now()).secs;
+        tx.execute(
+            "UPDATE scans SET finished_at = ?1 WHERE id = ?2",
+            [finished_at, scan],
+        )?;
+        tx.commit()?;
+        Ok(stats)
+    }
+}
+
+/// Summary of an [`IndexUpdate`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct IndexStats {
+    /// Number of entries not in the index before or marked as deleted.
+    pub added: usize,
+    /// Number of entries that changed since the previous scan.
+    pub updated: usize,
+    /// Number of entries newly marked as deleted.
+    pub deleted: usize,
+}
+
+/// Conditions of entries returned by [`Index::query`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    pub id: Option<String>,
+    pub user: Option<String>,
+    pub deleted: Deleted,
+}
+
+/// Treatment of entries marked as deleted in a [`Query`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Deleted {
+    #[default]
+    Exclude,
+    Include,
+    Only,
+}
+
+/// Entry stored in an [`Index`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedEntry {
+    /// The entry in its serialized form, including [`FileInfo`] fields.
+    pub entry: Value,
+    pub deleted_at: Option<Timestamp>,
+}
+
+/// Errors in accessing an [`Index`].
+#[derive(Debug)]
+pub enum IndexError {
+    Sqlite(rusqlite::Error),
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// The database was created by a newer version of lsmovie.
+    UnsupportedVersion(i64),
+}
+
+impl From<rusqlite::Error> for IndexError {
+    fn from(e: rusqlite::Error) -> Self {
+        Self::Sqlite(e)
+    }
+}
+
+impl From<io::Error> for IndexError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for IndexError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+impl fmt::Display for IndexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sqlite(e) => write!(f, "index database error: {e}"),
+            Self::Io(e) => write!(f, "{e}"),
+            Self::Json(e) => write!(f, "broken entry in index: {e}"),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported index schema version {v}"),
+        }
+    }
+}
+
+impl std::error::Error for IndexError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Sqlite(e) => Some(e),
+            Self::Io(e) => Some(e),
+            Self::Json(e) => Some(e),
+            Self::UnsupportedVersion(_) => None,
+        }
+    }
+}
+
+/// Returns a serialized entry without its [`VOLATILE_FIELDS`].
+fn content(mut entry: Value) -> Value {
+    if let Value::Object(map) = &mut entry {
+        for field in VOLATILE_FIELDS {
+            map.remove(field);
+        }
+    }
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, id: &str) -> MovieEntry {
+        let file = FileInfo {
+            absolute_path: PathBuf::from(path),
+            relative_path: None,
+            extension: "mp4".to_owned(),
+            size: 1,
+            mtime: None,
+            ctime: None,
+            inode: None,
+            device: None,
+        };
+        MovieEntry {
+            path: PathBuf::from(path),
+            id: id.to_owned(),
+            user: "@u".to_owned(),
+            file: Some(file),
+            ..Default::default()
+        }
+    }
+
+    fn ids(index: &Index, query: &Query) -> Vec<String> {
+        let entries = index.query(query).unwrap();
+        entries
+            .iter()
+            .map(|e| e.entry["id"].as_str().unwrap().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn vanished_files_are_marked_as_deleted() {
+        let mut index = Index::open_in_memory().unwrap();
+
+        let mut update = index.update(&["/a"]).unwrap();
+        update.upsert(&entry("/a/@u/1.mp4", "1")).unwrap();
+        update.upsert(&entry("/a/@u/2.mp4", "2")).unwrap();
+        update.upsert(&entry("/a/@v/3.mp4", "3")).unwrap();
+        update.finish().unwrap();
+
+        let mut update = index.update(&["/a/@u"]).unwrap();
+        update.upsert(&entry("/a/@u/1.mp4", "1")).unwrap();
+        let stats = update.finish().unwrap();
+        assert_eq!(
+            stats,
+            IndexStats {
+                added: 0,
+                updated: 0,
+                deleted: 1
+            }
+        );
+
+        assert_eq!(ids(&index, &Query::default()), ["1", "3"]);
+        let query = Query {
+            deleted: Deleted::Only,
+            ..Default::default()
+        };
+        assert_eq!(ids(&index, &query), ["2"]);
+
+        let mut update = index.update(&["/a"]).unwrap();
+        update.skip_dir("/a/@v").unwrap();
+        update.upsert(&entry("/a/@u/1.mp4", "1b")).unwrap();
+        update.upsert(&entry("/a/@u/2.mp4", "2")).unwrap();
+        let stats = update.finish().unwrap();
+        assert_eq!((stats.added, stats.updated), (1, 1));
+        assert_eq!(ids(&index, &Query::default()), ["1b", "2", "3"]);
+
+        // Scanning from another root or touching files is not an update.
+        let mut update = index.update(&["/a"]).unwrap();
+        let mut touched = entry("/a/@u/1.mp4", "1b");
+        let file = touched.file.as_mut().unwrap();
+        file.relative_path = Some(PathBuf::from("1.mp4"));
+        file.mtime = Some(Timestamp::from_secs(1));
+        file.ctime = Some(Timestamp::from_secs(1));
+        update.upsert(&touched).unwrap();
+        update.upsert(&entry("/a/@u/2.mp4", "2")).unwrap();
+        update.skip_dir("/a/@v").unwrap();
+        let stats = update.finish().unwrap();
+        assert_eq!((stats.added, stats.updated, stats.deleted), (0, 0, 0));
+
+        let mut update = index.update(&["/a"]).unwrap();
+        update.skip_path("/a/@u/1.info.json").unwrap();
+        update.skip_path("/a/@u/2.mp4").unwrap();
+        update.finish().unwrap();
+        assert_eq!(ids(&index, &Query::default()), ["1b", "2"]);
+    }
+}
@@ -0,0 +1,96 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::extensions;
+
+/// Subset of the metadata written by yt-dlp's `--write-info-json`.
+///
+/// Only the fields lsmovie makes use of are read; everything else in the
+/// file is ignored.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct InfoJson {
+    pub title: Option<String>,
+    pub uploader: Option<String>,
+    pub channel_id: Option<String>,
+    pub upload_date: Option<String>,
+    pub duration: Option<f64>,
+    pub description: Option<String>,
+    pub webpage_url: Option<String>,
+    pub tags: Option<Vec<String>>,
+}
+
+impl InfoJson {
+    /// Returns the path of the sidecar for a movie file, e.g.
+    /// `Title [id].info.json` for `Title [id].webm`.
+    pub fn sidecar_path<P: AsRef<Path>>(movie_path: P) -> PathBuf {
+        let path = movie_path.as_ref();
+        let mut name = extensions::stem(path).unwrap_or_default().to_owned();
+        name.push(".info.json");
+        path.with_file_name(name)
+    }
+
+    /// Returns `true` if the path is that of a sidecar, ending with
+    /// `.info.json`.
+    pub fn is_sidecar<P: AsRef<Path>>(path: P) -> bool {
+        path.as_ref()
+            .file_name()
+            .is_some_and(|name| name.as_encoded_bytes().ends_with(b".info.json"))
+    }
+
+    /// Reads the sidecar of a movie file, returning `Ok(None)` if there is
+    /// none.
+    pub fn read_for<P: AsRef<Path>>(movie_path: P) -> io::Result<Option<Self>> {
+        let path = Self::sidecar_path(movie_path);
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let info = serde_json::from_slice(&bytes)?;
+        Ok(Some(info))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sidecar_path() {
+        let actual = InfoJson::sidecar_path("@foo/v1.2 title [aBcDeFgHiJkL].webm");
+        let expected = Path::new("@foo/v1.2 title [aBcDeFgHiJkL].info.json");
+        assert_eq!(actual, expected);
+        let actual = InfoJson::sidecar_path("@foo/v1.2 title [aBcDeFgHiJkL]");
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn deserialization_ignores_unknown_fields_and_nulls() {
+        let json = r#"{
+            "id": "aBcDeFgHiJkL",
+            "uploader": "FooBar",
+            "channel_id": "UCxxxxxxxxxxxxxxxxxxxxxx",
+            "upload_date": "20241101",
+            "duration": 212,
+            "description": null,
+            "tags": ["a", "b"],
+            "formats": [{"format_id": "18"}]
+        }"#;
+        let actual: InfoJson = serde_json::from_str(json).unwrap();
+        let expected = InfoJson {
+            title: None,
+            uploader: Some("FooBar".to_owned()),
+            channel_id: Some("UCxxxxxxxxxxxxxxxxxxxxxx".to_owned()),
+            upload_date: Some("20241101".to_owned()),
+            duration: Some(212.0),
+            description: None,
+            webpage_url: None,
+            tags: Some(vec!["a".to_owned(), "b".to_owned()]),
+        };
+        assert_eq!(actual, expected);
+    }
+}
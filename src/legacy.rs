@@ -0,0 +1,100 @@
+//! Decoding of file names in legacy encodings.
+
+use std::{fmt, str::FromStr, sync::LazyLock};
+
+use encoding_rs::{EUC_JP, SHIFT_JIS};
+
+/// Characters of the bytes 0x80 to 0xFF in code page 437.
+const CP437_HIGH: [&str; 4] = [
+    "ÇüéâäàåçêëèïîìÄÅÉæÆôöòûùÿÖÜ¢£¥₧ƒ",
+    "áíóúñÑªº¿⌐¬½¼¡«»░▒▓│┤╡╢╖╕╣║╗╝╜╛┐",
+    "└┴┬├─┼╞╟╚╔╩╦╠═╬╧╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀",
+    "αßΓπΣσµτΦΘΩδ∞φε∩≡±≥≤⌠⌡÷≈°∙·√ⁿ²■\u{A0}",
+];
+
+/// Encoding of file names written by systems that did not use UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum LegacyEncoding {
+    /// Shift_JIS as extended by Windows, used on Japanese Windows.
+    #[serde(rename = "cp932")]
+    Cp932,
+    #[serde(rename = "euc-jp")]
+    EucJp,
+    /// The original IBM PC code page, used for file names in many zip
+    /// files. Any bytes decode in it, so it is only useful as a last resort.
+    #[serde(rename = "cp437")]
+    Cp437,
+}
+
+impl LegacyEncoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Cp932 => "cp932",
+            Self::EucJp => "euc-jp",
+            Self::Cp437 => "cp437",
+        }
+    }
+
+    /// Decodes bytes, or returns `None` if they are malformed in the
+    /// encoding.
+    pub fn decode(self, bytes: &[u8]) -> Option<String> {
+        let encoding = match self {
+            Self::Cp932 => SHIFT_JIS,
+            Self::EucJp => EUC_JP,
+            Self::Cp437 => {
+                static HIGH: LazyLock<Vec<char>> =
+                    LazyLock::new(|| CP437_HIGH.concat().chars().collect());
+                let decoded = bytes.iter().map(|&b| match b {
+                    0..0x80 => char::from(b),
+                    _ => HIGH[usize::from(b - 0x80)],
+                });
+                return Some(decoded.collect());
+            }
+        };
+        let decoded = encoding.decode_without_bom_handling_and_without_replacement(bytes)?;
+        Some(decoded.into_owned())
+    }
+}
+
+impl FromStr for LegacyEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "cp932" | "shift_jis" | "sjis" | "windows-31j" => Ok(Self::Cp932),
+            "euc-jp" | "eucjp" => Ok(Self::EucJp),
+            "cp437" | "ibm437" => Ok(Self::Cp437),
+            _ => Err(format!("unknown legacy encoding: {s:?}")),
+        }
+    }
+}
+
+impl fmt::Display for LegacyEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decoding() {
+        // "動画" in each encoding.
+        let cp932 = b"\x93\xae\x89\xe6";
+        let euc_jp = b"\xc6\xb0\xb2\xe8";
+        assert_eq!(LegacyEncoding::Cp932.decode(cp932).as_deref(), Some("動画"));
+        assert_eq!(
+            LegacyEncoding::EucJp.decode(euc_jp).as_deref(),
+            Some("動画")
+        );
+        assert_eq!(LegacyEncoding::EucJp.decode(cp932), None);
+        assert_eq!(
+            LegacyEncoding::Cp437.decode(b"caf\x82 \xff").as_deref(),
+            Some("café \u{A0}")
+        );
+        assert_eq!(CP437_HIGH.concat().chars().count(), 128);
+        assert_eq!("CP932".parse(), Ok(LegacyEncoding::Cp932));
+    }
+}
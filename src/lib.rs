@@ -0,0 +1,53 @@
+//! Listing of movie files downloaded into `@user` directories.
+//!
+//! Movie information is extracted from paths such as
+//! `@user/Title [id].webm`.
+
+mod cache;
+mod config;
+mod date;
+mod diff;
+mod dupes;
+mod entry;
+mod error;
+mod extensions;
+mod file_info;
+mod filter;
+mod index;
+mod info_json;
+mod legacy;
+mod output;
+mod platform;
+mod probe;
+mod scan;
+mod template;
+#[cfg(test)]
+mod testing;
+mod user_rule;
+mod walk;
+#[cfg(target_os = "linux")]
+mod watch;
+
+#[cfg(target_os = "linux")]
+pub use crate::watch::{Watch, WatchEvent};
+pub use crate::{
+    cache::ScanCache,
+    config::{Config, ConfigError, TemplateConfig},
+    date::{Date, ParseDateError, Timestamp},
+    diff::{diff, Change, ChangeKind, DiffError},
+    dupes::{find_duplicates, DuplicateFile, DuplicateGroup, KeepPreference},
+    entry::{MovieEntry, ParseOptions},
+    error::Error,
+    extensions::Extensions,
+    file_info::FileInfo,
+    filter::{Filter, InvalidPattern, IGNORE_FILE},
+    index::{Deleted, Index, IndexError, IndexStats, IndexUpdate, IndexedEntry, Query},
+    info_json::InfoJson,
+    legacy::LegacyEncoding,
+    output::{Fields, Format, Record, RecordWriter, UnknownField},
+    platform::Platform,
+    probe::{Container, Integrity, ProbeInfo, Verification},
+    scan::{Scan, Scanner},
+    template::{Template, TemplateError, TemplateMatch},
+    user_rule::UserRule,
+};
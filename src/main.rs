@@ -1,109 +1,771 @@
 use std::{
-    env, fs, io,
-    path::{Component, Path},
-    sync::LazyLock,
+    env,
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
 };
 
-use regex::Regex;
-
-#[derive(Debug, PartialEq, Eq, serde::Serialize)]
-struct MovieEntry {
-    id: String,
-    user: String,
-    title: String,
-}
-
-impl MovieEntry {
-    fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
-        let path = path.as_ref();
-        let stem = path
-            .file_stem()
-            .unwrap_or_default()
-            .to_str()
-            .unwrap_or_default();
-        let (id, title) = extract_id(stem)?;
-        let (id, title) = (id.to_owned(), title.to_owned());
-        let user = path
-            .components()
-            .rev()
-            .skip(1)
-            .find_map(|component| extract_user_name(&component))?;
-        Some(MovieEntry { id, user, title })
-    }
-}
-
-fn extract_id(stem: &str) -> Option<(&str, &str)> {
-    static RE: LazyLock<Regex> =
-        LazyLock::new(|| Regex::new(r"(?<title>.+)\s+\[(?<id>[^\]]+)\]$").unwrap());
-    let caps = RE.captures(stem)?;
-    let title = caps.name("title")?.as_str();
-    let id = caps.name("id")?.as_str();
-    Some((id, title))
-}
-
-fn extract_user_name(component: &Component) -> Option<String> {
-    let s = component.as_os_str().to_str()?;
-    if s.starts_with("@") {
-        Some(s.to_owned())
+use lsmovie::{
+    diff, find_duplicates, Config, Deleted, DuplicateGroup, Error, Extensions, Fields, Filter,
+    Format, Index, IndexedEntry, KeepPreference, LegacyEncoding, MovieEntry, Query, Record,
+    RecordWriter, ScanCache, Scanner, Template, UserRule, Verification,
+};
+
+const USAGE: &str = "\
+Usage: lsmovie [COMMAND] [OPTIONS] <DIR>...
+       lsmovie query [OPTIONS]
+       lsmovie diff [OPTIONS] <OLD> [NEW]
+       lsmovie watch [OPTIONS] <DIR>...
+
+Lists movie files under DIRs on stdout, as JSON lines by default.
+Errors are reported as JSON lines on stderr.
+
+Commands:
+  list    List movie files (default)
+  verify  Check movie file containers and report each file as ok,
+          truncated or corrupt, or as unchecked for containers other than
+          MP4 and Matroska/WebM, which are recognized but not parsed
+  dupes   Report groups of files sharing the same ID
+  index   Record movie files in the index database, marking files no
+          longer found under DIRs as deleted
+  query   List movie files from the index database instead of the file
+          system
+  diff    Compare entries in OLD and NEW, files written by list in jsonl
+          or json format, or OLD and the index if NEW is omitted. Entries
+          are matched by ID and reported as added, removed, moved between
+          users, or with changed titles or extensions; use --format table
+          for a readable report. Extensions are only compared between
+          entries with path or extension fields
+  watch   Report movie files added under DIRs once they are no longer
+          written to, and movie files removed, as it happens. Records
+          have an event field of added or removed
+  rename-plan
+          List movie files with names that are not valid UTF-8 but decode
+          with --legacy-encoding, with their raw_path, the utf8_path to
+          rename them to and the encoding
+
+Options:
+      --config <PATH>       Read settings from PATH instead of
+                            $XDG_CONFIG_HOME/lsmovie/config.toml
+      --extensions <LIST>   Comma-separated extensions of the files to list,
+                            matched case-insensitively, or presets: video
+                            (mkv, mp4, webm, m4v, mov, flv, ts, avi; the
+                            default) and audio (m4a, opus, mp3)
+      --sniff               Recognize the container of every file by its
+                            content, listing files with other extensions
+                            that are movies and reporting files with movie
+                            extensions that are not; files without an
+                            extension are always checked
+      --legacy-encoding <LIST>
+                            Decode names that are not valid UTF-8 with the
+                            first of the comma-separated encodings they are
+                            valid in: cp932, euc-jp or cp437
+      --original-names      Add original_title and original_user with the
+                            forms found in the path when they are not in
+                            Unicode NFC, which title and user are
+                            normalized to
+      --restore-title       Set title to the original title of the video,
+                            from the info.json sidecar or by reversing the
+                            yt-dlp filename substitutions, and add
+                            filename_title with the title in the file name
+      --template <SPEC>     Filename template as NAME=FORMAT, NAME=REGEX or
+                            the NAME of a built-in template; may be repeated
+                            and replaces templates from the config file
+      --user-rule <RULE>    Rule for finding the user of a movie: prefix=P,
+                            regex=REGEX, depth=N, uploader or literal=NAME;
+                            may be repeated, tried in order, and replaces
+                            rules from the config file [default: prefix=@]
+      --format <FORMAT>     Output format: jsonl (default), json, csv, tsv
+                            or table
+      --bom                 Start csv and tsv output with a UTF-8 BOM for
+                            spreadsheet applications
+      --fields <LIST>       Output only the comma-separated fields in LIST;
+                            besides entry fields, path, relative_path,
+                            extension, size, mtime, ctime, inode and device
+                            are available. Tabular formats default to
+                            id,user,title
+      --errors-file <PATH>  Write error records to PATH instead of stderr
+      --no-info-json        Do not read yt-dlp .info.json sidecars
+      --strip-date          Add title_without_date with the date in the
+                            title removed
+      --probe               Read duration, resolution and codecs from MP4
+                            and Matroska/WebM containers; files in other
+                            containers or in none get no probe field
+      --settle <SECS>       For watch, how long a file must go without being
+                            written to before it is reported [default: 5]
+  -j, --jobs <N>            Traverse directories and process files on N
+                            threads [default: 1]
+      --sort                Output entries sorted by path, after the scan
+                            has finished
+      --follow-symlinks     Descend into symbolic links to directories
+                            (default); links back to a directory being
+                            scanned are reported as symlink_loop errors
+      --no-follow-symlinks  Do not descend into symbolic links to
+                            directories; links to files are still listed
+      --one-file-system     Do not descend into directories on other file
+                            systems than their DIR
+      --exclude <GLOB>      Skip files and directories matching GLOB, a
+                            .gitignore pattern relative to DIR; may be
+                            repeated
+      --include <GLOB>      List only files matching GLOB or in a directory
+                            matching it; may be repeated
+      --no-ignore-files     Do not skip files and directories listed in
+                            .lsmovieignore files, which have the syntax of
+                            .gitignore files
+      --skip-hidden         Skip files and directories starting with a dot
+      --cache <PATH>        Reuse results for files and directories that
+                            have not changed since the last scan with the
+                            same cache file, and update it
+      --keep <PREF>         For dupes, which copy to suggest keeping:
+                            largest (default), newest or
+                            container:EXT,EXT,...; may be repeated to break
+                            ties
+      --index <PATH>        For index and query, the index database
+                            [default: $XDG_DATA_HOME/lsmovie/index.sqlite]
+      --id <ID>             For query, list only files with ID
+      --user <USER>         For query, list only files of USER
+      --deleted             For query, list only files marked as deleted
+      --include-deleted     For query, also list files marked as deleted
+  -h, --help                Print help
+
+Built-in templates: bracket (default), id-title, date-title-id, title-id
+
+Exit status is 0 on success, 1 if any error other than an unsupported
+extension or a broken cache, which is ignored, occurred or, for verify,
+any file is not ok, and 2 on invalid usage.";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    #[default]
+    List,
+    Verify,
+    Dupes,
+    Index,
+    Query,
+    Diff,
+    Watch,
+    RenamePlan,
+}
+
+#[derive(Debug, Default)]
+struct Options {
+    command: Command,
+    config: Option<PathBuf>,
+    extensions: Option<Extensions>,
+    sniff: bool,
+    legacy_encodings: Vec<LegacyEncoding>,
+    original_names: bool,
+    restore_title: bool,
+    templates: Vec<Template>,
+    user_rules: Vec<UserRule>,
+    format: Format,
+    bom: bool,
+    fields: Option<Fields>,
+    errors_file: Option<PathBuf>,
+    no_info_json: bool,
+    strip_date: bool,
+    probe: bool,
+    jobs: usize,
+    sort: bool,
+    no_follow_symlinks: bool,
+    one_file_system: bool,
+    exclude: Vec<String>,
+    include: Vec<String>,
+    no_ignore_files: bool,
+    skip_hidden: bool,
+    cache: Option<PathBuf>,
+    settle: Option<Duration>,
+    keep: Vec<KeepPreference>,
+    index: Option<PathBuf>,
+    query: Query,
+    dirs: Vec<PathBuf>,
+}
+
+/// Parses command line arguments, returning `None` if help is requested.
+fn parse_args<I: Iterator<Item = OsString>>(args: I) -> Result<Option<Options>, String> {
+    let mut args = args.peekable();
+    let mut opts = Options::default();
+    let command = match args.peek().and_then(|arg| arg.to_str()) {
+        Some("list") => Some(Command::List),
+        Some("verify") => Some(Command::Verify),
+        Some("dupes") => Some(Command::Dupes),
+        Some("index") => Some(Command::Index),
+        Some("query") => Some(Command::Query),
+        Some("diff") => Some(Command::Diff),
+        Some("watch") => Some(Command::Watch),
+        Some("rename-plan") => Some(Command::RenamePlan),
+        _ => None,
+    };
+    if let Some(command) = command {
+        opts.command = command;
+        args.next();
+    }
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("-h" | "--help") => return Ok(None),
+            Some("--config") => {
+                let value = args.next().ok_or("--config requires a value")?;
+                opts.config = Some(value.into());
+            }
+            Some("--extensions") => {
+                let value = args.next().ok_or("--extensions requires a value")?;
+                let value = value.to_str().ok_or("--extensions must be valid UTF-8")?;
+                opts.extensions = Some(value.parse()?);
+            }
+            Some("--sniff") => opts.sniff = true,
+            Some("--original-names") => opts.original_names = true,
+            Some("--restore-title") => opts.restore_title = true,
+            Some("--legacy-encoding") => {
+                let value = args.next().ok_or("--legacy-encoding requires a value")?;
+                let value = value
+                    .to_str()
+                    .ok_or("--legacy-encoding must be valid UTF-8")?;
+                opts.legacy_encodings = value
+                    .split(',')
+                    .map(|name| name.trim().parse())
+                    .collect::<Result<_, _>>()?;
+            }
+            Some("--template") => {
+                let value = args.next().ok_or("--template requires a value")?;
+                opts.templates.push(parse_template(&value)?);
+            }
+            Some("--user-rule") => {
+                let value = args.next().ok_or("--user-rule requires a value")?;
+                let value = value.to_str().ok_or("--user-rule must be valid UTF-8")?;
+                opts.user_rules.push(value.parse()?);
+            }
+            Some("--format") => {
+                let value = args.next().ok_or("--format requires a value")?;
+                let value = value.to_str().ok_or("--format must be valid UTF-8")?;
+                opts.format = value.parse()?;
+            }
+            Some("--bom") => opts.bom = true,
+            Some("--fields") => {
+                let value = args.next().ok_or("--fields requires a value")?;
+                let value = value.to_str().ok_or("--fields must be valid UTF-8")?;
+                opts.fields = Some(Fields::parse(value).map_err(|e| e.to_string())?);
+            }
+            Some("--errors-file") => {
+                let value = args.next().ok_or("--errors-file requires a value")?;
+                opts.errors_file = Some(value.into());
+            }
+            Some("--no-info-json") => opts.no_info_json = true,
+            Some("--strip-date") => opts.strip_date = true,
+            Some("--probe") => opts.probe = true,
+            Some("-j" | "--jobs") => {
+                let value = args.next().ok_or("--jobs requires a value")?;
+                opts.jobs = value
+                    .to_str()
+                    .and_then(|value| value.parse().ok())
+                    .filter(|&jobs| jobs > 0)
+                    .ok_or("--jobs must be a positive integer")?;
+            }
+            Some("--sort") => opts.sort = true,
+            Some("--follow-symlinks") => opts.no_follow_symlinks = false,
+            Some("--no-follow-symlinks") => opts.no_follow_symlinks = true,
+            Some("--one-file-system") => opts.one_file_system = true,
+            Some(flag @ ("--exclude" | "--include")) => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("{flag} requires a value"))?;
+                let value = value
+                    .into_string()
+                    .map_err(|_| format!("{flag} must be valid UTF-8"))?;
+                match flag {
+                    "--exclude" => opts.exclude.push(value),
+                    _ => opts.include.push(value),
+                }
+            }
+            Some("--no-ignore-files") => opts.no_ignore_files = true,
+            Some("--skip-hidden") => opts.skip_hidden = true,
+            Some("--settle") => {
+                let value = args.next().ok_or("--settle requires a value")?;
+                let secs = value
+                    .to_str()
+                    .and_then(|value| value.parse::<f64>().ok())
+                    .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                    .ok_or("--settle must be a number of seconds")?;
+                opts.settle = Some(secs);
+            }
+            Some("--cache") => {
+                let value = args.next().ok_or("--cache requires a value")?;
+                opts.cache = Some(value.into());
+            }
+            Some("--keep") => {
+                let value = args.next().ok_or("--keep requires a value")?;
+                let value = value.to_str().ok_or("--keep must be valid UTF-8")?;
+                opts.keep.push(value.parse()?);
+            }
+            Some("--index") => {
+                let value = args.next().ok_or("--index requires a value")?;
+                opts.index = Some(value.into());
+            }
+            Some("--id") => {
+                let value = args.next().ok_or("--id requires a value")?;
+                let value = value
+                    .into_string()
+                    .map_err(|_| "--id must be valid UTF-8")?;
+                opts.query.id = Some(value);
+            }
+            Some("--user") => {
+                let value = args.next().ok_or("--user requires a value")?;
+                let value = value
+                    .into_string()
+                    .map_err(|_| "--user must be valid UTF-8")?;
+                opts.query.user = Some(value);
+            }
+            Some("--deleted") => opts.query.deleted = Deleted::Only,
+            Some("--include-deleted") => opts.query.deleted = Deleted::Include,
+            Some("--") => {
+                opts.dirs.extend(args.by_ref().map(PathBuf::from));
+            }
+            Some(s) if s.starts_with('-') && s != "-" => {
+                return Err(format!("unknown option: {s}"));
+            }
+            _ => opts.dirs.push(arg.into()),
+        }
+    }
+    if opts.command == Command::Query && !opts.dirs.is_empty() {
+        return Err("query does not take DIR arguments".to_owned());
+    }
+    if opts.command == Command::Diff && !(1..=2).contains(&opts.dirs.len()) {
+        return Err("diff takes one or two files".to_owned());
+    }
+    if opts.command == Command::RenamePlan && opts.legacy_encodings.is_empty() {
+        return Err("rename-plan requires --legacy-encoding".to_owned());
+    }
+    if opts.command == Command::Watch && matches!(opts.format, Format::Json | Format::Table) {
+        return Err("watch does not support json and table output".to_owned());
+    }
+    Ok(Some(opts))
+}
+
+fn parse_template(spec: &OsString) -> Result<Template, String> {
+    let spec = spec.to_str().ok_or("--template must be valid UTF-8")?;
+    match spec.split_once('=') {
+        Some((name, spec)) => {
+            Template::parse(name, spec).map_err(|e| format!("template {name:?}: {e}"))
+        }
+        None => Template::preset(spec).ok_or_else(|| format!("unknown template: {spec:?}")),
+    }
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(dir.join("lsmovie").join("config.toml"))
+}
+
+fn default_index_path() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))?;
+    Some(dir.join("lsmovie").join("index.sqlite"))
+}
+
+fn open_index(opts: &Options) -> io::Result<Index> {
+    let path = match &opts.index {
+        Some(path) => path.clone(),
+        None => {
+            default_index_path().ok_or_else(|| io::Error::other("no index path; use --index"))?
+        }
+    };
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    Index::open(&path).map_err(|e| io::Error::other(format!("{}: {e}", path.display())))
+}
+
+/// Reads a cache file, returning an empty cache along with the error if it
+/// is broken.
+fn load_cache(path: &Path) -> io::Result<(ScanCache, Option<Error>)> {
+    match ScanCache::load(path) {
+        Ok(cache) => Ok((cache, None)),
+        Err(source)
+            if matches!(
+                source.kind(),
+                io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+            ) =>
+        {
+            let path = path.to_path_buf();
+            Ok((ScanCache::new(), Some(Error::InvalidCache { path, source })))
+        }
+        Err(e) => Err(io::Error::other(format!("{}: {e}", path.display()))),
+    }
+}
+
+fn load_config(opts: &Options) -> Result<Config, String> {
+    let path = match &opts.config {
+        Some(path) => path.clone(),
+        None => match default_config_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(Config::default()),
+        },
+    };
+    Config::load(&path).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+fn build_scanner(opts: &mut Options, config: &Config) -> Result<Scanner, String> {
+    let mut scanner = Scanner::new()
+        .info_json(!opts.no_info_json)
+        .sniff(opts.sniff)
+        .legacy_encodings(opts.legacy_encodings.clone())
+        .original_names(opts.original_names)
+        .restore_title(opts.restore_title)
+        .strip_date(opts.strip_date)
+        .probe(opts.probe)
+        .verify(opts.command == Command::Verify)
+        .jobs(opts.jobs)
+        .sort(opts.sort)
+        .follow_symlinks(!opts.no_follow_symlinks)
+        .same_file_system(opts.one_file_system)
+        .filter(Filter::new(&opts.exclude, &opts.include).map_err(|e| e.to_string())?)
+        .ignore_files(!opts.no_ignore_files)
+        .skip_hidden(opts.skip_hidden)
+        .file_info(
+            opts.command == Command::Index
+                || opts.fields.as_ref().is_some_and(Fields::needs_file_info),
+        );
+    let templates = if opts.templates.is_empty() {
+        config.templates().map_err(|e| e.to_string())?
     } else {
-        None
+        Some(std::mem::take(&mut opts.templates))
+    };
+    if let Some(templates) = templates {
+        scanner = scanner.templates(templates);
     }
+    let user_rules = if opts.user_rules.is_empty() {
+        config.user_rules().map_err(|e| e.to_string())?
+    } else {
+        Some(std::mem::take(&mut opts.user_rules))
+    };
+    if let Some(user_rules) = user_rules {
+        scanner = scanner.user_rules(user_rules);
+    }
+    let extensions = match opts.extensions.take() {
+        Some(extensions) => Some(extensions),
+        None => config.extensions().map_err(|e| e.to_string())?,
+    };
+    if let Some(extensions) = extensions {
+        scanner = scanner.extensions(extensions);
+    }
+    Ok(scanner)
 }
 
-fn visit_dir<P: AsRef<Path>>(dir: P, cb: &dyn Fn(&fs::DirEntry)) -> io::Result<()> {
-    let dir = dir.as_ref();
-    if dir.is_dir() {
-        for entry in fs::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_dir() {
-                visit_dir(path, cb)?;
-            } else {
-                cb(&entry);
+/// Converts an entry into a record in the form for the command, returning
+/// `false` along with it if the entry indicates a failure.
+fn entry_record(opts: &Options, mut entry: MovieEntry) -> (Record, bool) {
+    match opts.command {
+        Command::List
+        | Command::Dupes
+        | Command::Index
+        | Command::Query
+        | Command::Diff
+        | Command::Watch => {
+            let record = match &opts.fields {
+                Some(fields) => fields.select(&entry),
+                None if opts.format.is_tabular() => Fields::tabular_default().select(&entry),
+                None => Record::from_serialize(&entry),
+            };
+            (record, true)
+        }
+        Command::Verify => {
+            let verification = entry.integrity.take().unwrap_or_else(Verification::ok);
+            let ok = !verification.is_failure();
+            let Verification { status, reason } = verification;
+            let record = Record(vec![
+                ("path".to_owned(), entry.path.to_string_lossy().into()),
+                ("id".to_owned(), entry.id.into()),
+                ("status".to_owned(), serde_json::to_value(status).unwrap()),
+                ("reason".to_owned(), reason.into()),
+            ]);
+            (record, ok)
+        }
+        Command::RenamePlan => {
+            let record = Record(vec![
+                ("raw_path".to_owned(), entry.raw_path.into()),
+                ("utf8_path".to_owned(), entry.utf8_path.into()),
+                (
+                    "encoding".to_owned(),
+                    serde_json::to_value(entry.encoding).unwrap(),
+                ),
+            ]);
+            (record, true)
+        }
+    }
+}
+
+/// Reports changes under the directories until watching fails.
+#[cfg(target_os = "linux")]
+fn watch<W: Write>(
+    opts: &Options,
+    scanner: &Scanner,
+    out: &mut RecordWriter<W>,
+    errors: &mut dyn Write,
+) -> io::Result<bool> {
+    use lsmovie::WatchEvent;
+
+    let mut watch = scanner
+        .watch(&opts.dirs)
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    if let Some(settle) = opts.settle {
+        watch = watch.settle(settle);
+    }
+    let mut failed = false;
+    for result in watch {
+        let (event, entry) = match result {
+            Ok(WatchEvent::Added(entry)) => ("added", entry),
+            Ok(WatchEvent::Removed(entry)) => ("removed", entry),
+            Err(e) => {
+                failed |= e.is_failure();
+                let j = serde_json::to_string(&e).expect("JSON serialization failed");
+                writeln!(errors, "{}", j)?;
+                errors.flush()?;
+                continue;
             }
+        };
+        let path = entry.path.to_string_lossy().into_owned();
+        let (mut record, _) = entry_record(opts, entry);
+        if event == "removed" && record.get("path").is_none() {
+            record.0.insert(0, ("path".to_owned(), path.into()));
         }
+        record.0.insert(0, ("event".to_owned(), event.into()));
+        out.write(&record)?;
+        out.flush()?;
     }
-    Ok(())
+    Ok(!failed)
 }
 
-const EXTENSIONS: [&'static str; 3] = ["mkv", "mp4", "webm"];
+#[cfg(not(target_os = "linux"))]
+fn watch<W: Write>(
+    _opts: &Options,
+    _scanner: &Scanner,
+    _out: &mut RecordWriter<W>,
+    _errors: &mut dyn Write,
+) -> io::Result<bool> {
+    Err(io::Error::other("watch is only supported on Linux"))
+}
 
-fn process(entry: &fs::DirEntry) {
-    let path = entry.path();
-    if let Some(ext) = path.extension() {
-        if !EXTENSIONS.contains(&ext.to_str().unwrap_or_default()) {
-            eprintln!("ignored: {:?}", &path);
-            return;
+/// Converts an entry read from the index into a record.
+fn indexed_record(opts: &Options, indexed: IndexedEntry) -> Record {
+    let IndexedEntry { entry, deleted_at } = indexed;
+    match &opts.fields {
+        Some(fields) => fields.select_value(entry),
+        None if opts.format.is_tabular() => Fields::tabular_default().select_value(entry),
+        None => {
+            let mut record = Record::from_serialize(&entry);
+            if let Some(deleted_at) = deleted_at {
+                let deleted_at = serde_json::to_value(deleted_at).unwrap();
+                record.0.push(("deleted_at".to_owned(), deleted_at));
+            }
+            record
         }
     }
-    if let Some(entry) = MovieEntry::from_path(&path) {
-        let j = serde_json::to_string(&entry).expect("JSON serialization failed");
-        println!("{}", j)
-    } else {
-        eprintln!("movie info extraction failed: {:?}", &path)
+}
+
+/// Reads entries written by the list command in jsonl or json format.
+fn read_entries(path: &Path) -> io::Result<Vec<serde_json::Value>> {
+    let context = |line: Option<usize>, e: serde_json::Error| {
+        let path = path.display();
+        match line {
+            Some(line) => io::Error::other(format!("{path}:{line}: {e}")),
+            None => io::Error::other(format!("{path}: {e}")),
+        }
+    };
+    let data = fs::read_to_string(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+    if data.trim_start().starts_with('[') {
+        return serde_json::from_str(&data).map_err(|e| context(None, e));
     }
+    data.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| serde_json::from_str(line).map_err(|e| context(Some(i + 1), e)))
+        .collect()
 }
 
-fn main() {
-    let args = env::args().skip(1);
-    for arg in args {
-        let _ = visit_dir(arg, &process);
+/// Converts a group of duplicates into records, one per file in tabular
+/// formats and one per group otherwise.
+fn group_records(format: Format, group: DuplicateGroup) -> Vec<Record> {
+    if !format.is_tabular() {
+        return vec![Record::from_serialize(&group)];
     }
+    group
+        .files
+        .iter()
+        .map(|file| {
+            let mut record = Record::from_serialize(file);
+            record
+                .0
+                .insert(0, ("id".to_owned(), group.id.clone().into()));
+            record
+        })
+        .collect()
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+fn run(opts: Options, scanner: Scanner) -> io::Result<bool> {
+    let mut errors: Box<dyn Write> = match &opts.errors_file {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stderr().lock()),
+    };
+    let mut stdout = io::stdout().lock();
+    if opts.bom && matches!(opts.format, Format::Csv | Format::Tsv) {
+        stdout.write_all("\u{FEFF}".as_bytes())?;
+    }
+    let mut out = RecordWriter::new(stdout, opts.format);
+    let mut failed = false;
+    let mut entries = Vec::new();
+
+    if opts.command == Command::Query {
+        for indexed in open_index(&opts)?
+            .query(&opts.query)
+            .map_err(io::Error::other)?
+        {
+            out.write(&indexed_record(&opts, indexed))?;
+        }
+        out.finish()?.flush()?;
+        return Ok(true);
+    }
+    if opts.command == Command::Watch {
+        let ok = watch(&opts, &scanner, &mut out, &mut errors)?;
+        out.finish()?.flush()?;
+        return Ok(ok);
+    }
+    if opts.command == Command::Diff {
+        let old = read_entries(&opts.dirs[0])?;
+        let new = match opts.dirs.get(1) {
+            Some(path) => read_entries(path)?,
+            None => open_index(&opts)?
+                .query(&Query::default())
+                .map_err(io::Error::other)?
+                .into_iter()
+                .map(|indexed| indexed.entry)
+                .collect(),
+        };
+        for change in diff(&old, &new).map_err(io::Error::other)? {
+            let record = match &opts.fields {
+                Some(fields) => fields.select_value(serde_json::to_value(&change).unwrap()),
+                None => Record::from_serialize(&change),
+            };
+            out.write(&record)?;
+        }
+        out.finish()?.flush()?;
+        return Ok(true);
+    }
+    let mut index = match opts.command {
+        Command::Index => Some(open_index(&opts)?),
+        _ => None,
+    };
+    let mut update = match &mut index {
+        Some(index) => Some(index.update(&opts.dirs).map_err(io::Error::other)?),
+        None => None,
+    };
 
-    #[test]
-    fn movie_info_extraction() {
-        let path = Path::new("./@path/to/@foobar/baz/@FooBar (2024年11月1日) [aBcDeFgHiJkL].webm");
-        let actual = MovieEntry::from_path(&path);
-        let expected = Some(MovieEntry {
-            id: "aBcDeFgHiJkL".to_owned(),
-            user: "@foobar".to_owned(),
-            title: "@FooBar (2024年11月1日)".to_owned(),
-        });
-        assert_eq!(actual, expected);
+    let mut scan = scanner.scan(&opts.dirs);
+    if let Some(path) = &opts.cache {
+        let (cache, error) = load_cache(path)?;
+        if let Some(e) = error {
+            failed |= e.is_failure();
+            let j = serde_json::to_string(&e).expect("JSON serialization failed");
+            writeln!(errors, "{}", j)?;
+        }
+        scan = scan.with_cache(cache);
+    }
+    for result in &mut scan {
+        match result {
+            Ok(entry) if opts.command == Command::Dupes => entries.push(entry),
+            Ok(entry) if opts.command == Command::Index => {
+                let update = update.as_mut().unwrap();
+                update.upsert(&entry).map_err(io::Error::other)?;
+            }
+            Ok(entry) if opts.command == Command::RenamePlan && entry.utf8_path.is_none() => {}
+            Ok(entry) => {
+                let (record, ok) = entry_record(&opts, entry);
+                out.write(&record)?;
+                failed |= !ok;
+            }
+            Err(e) => {
+                if let Some(update) = &mut update {
+                    // Files that fail for reasons that may go away keep their
+                    // rows, while those no longer selected or parsed are
+                    // marked as deleted.
+                    let skipped = match &e {
+                        Error::UnreadableDir { path, .. } => update.skip_dir(path),
+                        Error::UnreadableMetadata { path, .. }
+                        | Error::InvalidInfoJson { path, .. }
+                        | Error::ProbeFailed { path, .. } => update.skip_path(path),
+                        _ => Ok(()),
+                    };
+                    skipped.map_err(io::Error::other)?;
+                }
+                failed |= e.is_failure();
+                let j = serde_json::to_string(&e).expect("JSON serialization failed");
+                writeln!(errors, "{}", j)?;
+            }
+        }
+    }
+    if let (Some(path), Some(cache)) = (&opts.cache, scan.into_cache()) {
+        cache
+            .save(path)
+            .map_err(|e| io::Error::other(format!("{}: {e}", path.display())))?;
+    }
+
+    if let Some(update) = update {
+        let stats = update.finish().map_err(io::Error::other)?;
+        out.write(&Record::from_serialize(&stats))?;
+    }
+    if opts.command == Command::Dupes {
+        let keep = if opts.keep.is_empty() {
+            vec![KeepPreference::Largest]
+        } else {
+            opts.keep
+        };
+        let (groups, unreadable) = find_duplicates(entries, &keep);
+        for e in unreadable {
+            failed |= e.is_failure();
+            let j = serde_json::to_string(&e).expect("JSON serialization failed");
+            writeln!(errors, "{}", j)?;
+        }
+        for group in groups {
+            for record in group_records(opts.format, group) {
+                out.write(&record)?;
+            }
+        }
+    }
+    errors.flush()?;
+    out.finish()?.flush()?;
+    Ok(!failed)
+}
+
+fn main() -> ExitCode {
+    let mut opts = match parse_args(env::args_os().skip(1)) {
+        Ok(Some(opts)) => opts,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(msg) => {
+            eprintln!("lsmovie: {msg}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    let scanner = match load_config(&opts).and_then(|config| build_scanner(&mut opts, &config)) {
+        Ok(scanner) => scanner,
+        Err(msg) => {
+            eprintln!("lsmovie: {msg}");
+            return ExitCode::from(2);
+        }
+    };
+    match run(opts, scanner) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("lsmovie: {e}");
+            ExitCode::FAILURE
+        }
     }
 }
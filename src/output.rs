@@ -0,0 +1,380 @@
+use std::{
+    fmt,
+    io::{self, Write},
+    str::FromStr,
+};
+
+use serde::ser::SerializeMap;
+use serde_json::Value;
+use unicode_width::UnicodeWidthStr;
+
+use crate::{FileInfo, MovieEntry};
+
+/// Ordered selection of entry fields to output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fields(Vec<String>);
+
+impl Fields {
+    /// Parses a comma-separated list of field names.
+    pub fn parse(list: &str) -> Result<Self, UnknownField> {
+        let fields = list
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                let known = MovieEntry::FIELDS.contains(&name) || FileInfo::FIELDS.contains(&name);
+                known
+                    .then(|| name.to_owned())
+                    .ok_or_else(|| UnknownField(name.to_owned()))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self(fields))
+    }
+
+    /// Returns the fields used for tabular output when none are selected.
+    pub fn tabular_default() -> Self {
+        Self(vec!["id".to_owned(), "user".to_owned(), "title".to_owned()])
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(String::as_str)
+    }
+
+    /// Returns `true` if any field requires [`FileInfo`].
+    pub fn needs_file_info(&self) -> bool {
+        self.names().any(|name| FileInfo::FIELDS.contains(&name))
+    }
+
+    /// Picks the selected fields of an entry. Fields the entry does not have
+    /// are `null`.
+    pub fn select(&self, entry: &MovieEntry) -> Record {
+        let value = serde_json::to_value(entry).expect("JSON serialization failed");
+        self.select_value(value)
+    }
+
+    /// Same as [`Fields::select`] for an entry already serialized as a JSON
+    /// object.
+    pub fn select_value(&self, mut value: Value) -> Record {
+        let fields = self
+            .names()
+            .map(|name| {
+                let v = value.get_mut(name).map(Value::take).unwrap_or_default();
+                (name.to_owned(), v)
+            })
+            .collect();
+        Record(fields)
+    }
+}
+
+/// Field values of an entry in a fixed order, serialized as a map.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record(pub Vec<(String, Value)>);
+
+impl Record {
+    /// Creates a record from the fields of a value serialized as a map, in
+    /// serialization order.
+    pub fn from_serialize<T: serde::Serialize>(value: &T) -> Self {
+        match serde_json::to_value(value).expect("JSON serialization failed") {
+            Value::Object(map) => Self(map.into_iter().collect()),
+            value => Self(vec![("value".to_owned(), value)]),
+        }
+    }
+
+    /// Returns the value of a field.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.0.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+    }
+}
+
+impl serde::Serialize for Record {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, value) in &self.0 {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+/// Output format of records.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// One JSON object per line.
+    #[default]
+    Jsonl,
+    /// A single JSON array.
+    Json,
+    Csv,
+    Tsv,
+    /// Columns aligned with spaces, accounting for East Asian wide
+    /// characters.
+    Table,
+}
+
+impl Format {
+    pub fn is_tabular(self) -> bool {
+        matches!(self, Self::Csv | Self::Tsv | Self::Table)
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jsonl" => Ok(Self::Jsonl),
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            "tsv" => Ok(Self::Tsv),
+            "table" => Ok(Self::Table),
+            _ => Err(format!("unknown format: {s:?}")),
+        }
+    }
+}
+
+/// Writer of records in a [`Format`].
+///
+/// In tabular formats, the columns are the fields of the first record and a
+/// header row is written. The table format and the JSON array are only
+/// written out by [`RecordWriter::finish`].
+#[derive(Debug)]
+pub struct RecordWriter<W: Write> {
+    out: W,
+    format: Format,
+    columns: Option<Vec<String>>,
+    rows: Vec<Vec<String>>,
+    count: usize,
+}
+
+impl<W: Write> RecordWriter<W> {
+    pub fn new(out: W, format: Format) -> Self {
+        Self {
+            out,
+            format,
+            columns: None,
+            rows: Vec::new(),
+            count: 0,
+        }
+    }
+
+    pub fn write(&mut self, record: &Record) -> io::Result<()> {
+        match self.format {
+            Format::Jsonl => {
+                serde_json::to_writer(&mut self.out, record)?;
+                writeln!(self.out)?;
+            }
+            Format::Json => {
+                self.out
+                    .write_all(if self.count == 0 { b"[\n" } else { b",\n" })?;
+                serde_json::to_writer(&mut self.out, record)?;
+            }
+            Format::Csv | Format::Tsv | Format::Table => {
+                let columns = self
+                    .columns
+                    .get_or_insert_with(|| record.0.iter().map(|(name, _)| name.clone()).collect());
+                let row = columns
+                    .iter()
+                    .map(|name| record.get(name).map(cell).unwrap_or_default())
+                    .collect::<Vec<_>>();
+                if self.format == Format::Table {
+                    self.rows.push(row);
+                } else {
+                    if self.count == 0 {
+                        let header = columns.clone();
+                        self.write_delimited(&header)?;
+                    }
+                    self.write_delimited(&row)?;
+                }
+            }
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Flushes the underlying writer. Output buffered until
+    /// [`RecordWriter::finish`] is not written out.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Writes out buffered output and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        match self.format {
+            Format::Json if self.count == 0 => self.out.write_all(b"[]\n")?,
+            Format::Json => self.out.write_all(b"\n]\n")?,
+            Format::Table => self.write_table()?,
+            _ => {}
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn write_delimited(&mut self, row: &[String]) -> io::Result<()> {
+        let (sep, escape): (&str, fn(&str) -> String) = match self.format {
+            Format::Tsv => ("\t", escape_tsv),
+            _ => (",", escape_csv),
+        };
+        let line = row.iter().map(|s| escape(s)).collect::<Vec<_>>().join(sep);
+        // RFC 4180 specifies CRLF line breaks for CSV.
+        let eol = if self.format == Format::Csv {
+            "\r\n"
+        } else {
+            "\n"
+        };
+        write!(self.out, "{line}{eol}")
+    }
+
+    fn write_table(&mut self) -> io::Result<()> {
+        let Some(columns) = &self.columns else {
+            return Ok(());
+        };
+        let rows = self
+            .rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|s| s.replace(['\t', '\n', '\r'], " "))
+                    .collect()
+            })
+            .collect::<Vec<Vec<_>>>();
+        let widths = columns
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                rows.iter()
+                    .map(|row| row[i].width())
+                    .chain([name.width()])
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+        let rule = widths.iter().map(|w| "-".repeat(*w)).collect::<Vec<_>>();
+        for row in [columns, &rule].into_iter().chain(&rows) {
+            let mut line = String::new();
+            for (i, (s, width)) in row.iter().zip(&widths).enumerate() {
+                if i > 0 {
+                    line.push_str("  ");
+                }
+                line.push_str(s);
+                if i + 1 < row.len() {
+                    line.push_str(&" ".repeat(width - s.width()));
+                }
+            }
+            writeln!(self.out, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders a value as a cell of tabular output. Arrays of scalars are
+/// joined with `; ` and objects are written as JSON.
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Array(values) if values.iter().all(|v| !v.is_array() && !v.is_object()) => {
+            values.iter().map(cell).collect::<Vec<_>>().join("; ")
+        }
+        value => value.to_string(),
+    }
+}
+
+fn escape_csv(s: &str) -> String {
+    if s.contains([',', '"', '\r', '\n']) || s.starts_with(' ') || s.ends_with(' ') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+fn escape_tsv(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownField(pub String);
+
+impl fmt::Display for UnknownField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown field: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownField {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selection_keeps_order_and_fills_nulls() {
+        let entry = MovieEntry::from_path("@foobar/Title [aBcDeFgHiJkL].webm").unwrap();
+        let fields = Fields::parse("title, uploader,id").unwrap();
+        let actual = serde_json::to_string(&fields.select(&entry)).unwrap();
+        let expected = r#"{"title":"Title","uploader":null,"id":"aBcDeFgHiJkL"}"#;
+        assert_eq!(actual, expected);
+        assert!(!fields.needs_file_info());
+    }
+
+    fn render(format: Format, records: &[Record]) -> String {
+        let mut writer = RecordWriter::new(Vec::new(), format);
+        for record in records {
+            writer.write(record).unwrap();
+        }
+        String::from_utf8(writer.finish().unwrap()).unwrap()
+    }
+
+    fn records() -> Vec<Record> {
+        let record = |id: &str, title: &str| {
+            Record(vec![
+                ("id".to_owned(), Value::from(id)),
+                ("title".to_owned(), Value::from(title)),
+                ("tags".to_owned(), serde_json::json!(["a", "b"])),
+            ])
+        };
+        vec![
+            record("x1", "Foo, \"Bar\""),
+            record("x2", "日本語\tタイトル"),
+        ]
+    }
+
+    #[test]
+    fn delimited_formats() {
+        let expected =
+            "id,title,tags\r\nx1,\"Foo, \"\"Bar\"\"\",a; b\r\nx2,日本語\tタイトル,a; b\r\n";
+        assert_eq!(render(Format::Csv, &records()), expected);
+
+        let expected = "id\ttitle\ttags\nx1\tFoo, \"Bar\"\ta; b\nx2\t日本語\\tタイトル\ta; b\n";
+        assert_eq!(render(Format::Tsv, &records()), expected);
+    }
+
+    #[test]
+    fn table_format_with_wide_characters() {
+        let expected = "\
+id  title            tags
+--  ---------------  ----
+x1  Foo, \"Bar\"       a; b
+x2  日本語 タイトル  a; b
+";
+        assert_eq!(render(Format::Table, &records()), expected);
+    }
+
+    #[test]
+    fn json_formats() {
+        let records = &records()[..1];
+        let expected = "{\"id\":\"x1\",\"title\":\"Foo, \\\"Bar\\\"\",\"tags\":[\"a\",\"b\"]}\n";
+        assert_eq!(render(Format::Jsonl, records), expected);
+        assert_eq!(render(Format::Json, records), format!("[\n{}]\n", expected));
+        assert_eq!(render(Format::Json, &[]), "[]\n");
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let actual = Fields::parse("id,nope");
+        assert_eq!(actual, Err(UnknownField("nope".to_owned())));
+        assert!(Fields::parse("id,mtime").unwrap().needs_file_info());
+    }
+}
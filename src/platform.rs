@@ -0,0 +1,126 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+/// Video sharing platform a movie ID belongs to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    YouTube,
+    Niconico,
+    Twitch,
+    Vimeo,
+    Bilibili,
+    /// The ID matches no known platform shape.
+    #[default]
+    Unknown,
+}
+
+impl Platform {
+    /// Guesses the platform from the shape of an ID.
+    ///
+    /// Shapes are checked from the most specific, so that e.g. `sm12345678`
+    /// is classified as niconico even though it would also be a valid
+    /// YouTube ID.
+    pub fn detect(id: &str) -> Self {
+        static SHAPES: LazyLock<[(Platform, Regex); 5]> = LazyLock::new(|| {
+            [
+                (Platform::Niconico, r"^(sm|so|nm)\d+$"),
+                (Platform::Twitch, r"^v\d+$"),
+                (Platform::Bilibili, r"^BV[0-9A-Za-z]{10}$"),
+                (Platform::Vimeo, r"^\d+$"),
+                (Platform::YouTube, r"^[0-9A-Za-z_-]{11}$"),
+            ]
+            .map(|(platform, re)| (platform, Regex::new(re).unwrap()))
+        });
+        SHAPES
+            .iter()
+            .find(|(_, re)| re.is_match(id))
+            .map_or(Self::Unknown, |(platform, _)| *platform)
+    }
+
+    /// Guesses the platform from the host of a page URL such as yt-dlp's
+    /// `webpage_url`.
+    pub fn from_url(url: &str) -> Self {
+        let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+        let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+        let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+        let host = host.split(':').next().unwrap_or_default();
+        let is = |domain: &str| host == domain || host.ends_with(&format!(".{domain}"));
+        if is("youtube.com") || is("youtu.be") {
+            Self::YouTube
+        } else if is("nicovideo.jp") || is("nico.ms") {
+            Self::Niconico
+        } else if is("twitch.tv") {
+            Self::Twitch
+        } else if is("vimeo.com") {
+            Self::Vimeo
+        } else if is("bilibili.com") {
+            Self::Bilibili
+        } else {
+            Self::Unknown
+        }
+    }
+
+    /// Reconstructs the canonical URL of a video with the given ID.
+    pub fn url(self, id: &str) -> Option<String> {
+        let url = match self {
+            Self::YouTube => format!("https://www.youtube.com/watch?v={id}"),
+            Self::Niconico => format!("https://www.nicovideo.jp/watch/{id}"),
+            Self::Twitch => {
+                let id = id.strip_prefix('v').unwrap_or(id);
+                format!("https://www.twitch.tv/videos/{id}")
+            }
+            Self::Vimeo => format!("https://vimeo.com/{id}"),
+            Self::Bilibili => format!("https://www.bilibili.com/video/{id}"),
+            Self::Unknown => return None,
+        };
+        Some(url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detection_from_id() {
+        let cases = [
+            ("aBcDeFgHiJkL", Platform::Unknown),
+            ("aBcDeFgHi-_", Platform::YouTube),
+            ("sm12345678", Platform::Niconico),
+            ("so123456789", Platform::Niconico),
+            ("v1234567890", Platform::Twitch),
+            ("76979871", Platform::Vimeo),
+            ("BV1xx411c7mD", Platform::Bilibili),
+            ("some id", Platform::Unknown),
+        ];
+        for (id, expected) in cases {
+            assert_eq!(Platform::detect(id), expected, "{id}");
+        }
+    }
+
+    #[test]
+    fn detection_from_url() {
+        let cases = [
+            (
+                "https://www.youtube.com/watch?v=aBcDeFgHi-_",
+                Platform::YouTube,
+            ),
+            ("https://youtu.be/aBcDeFgHi-_", Platform::YouTube),
+            ("https://www.nicovideo.jp/watch/sm9", Platform::Niconico),
+            ("https://notyoutube.com/watch", Platform::Unknown),
+        ];
+        for (url, expected) in cases {
+            assert_eq!(Platform::from_url(url), expected, "{url}");
+        }
+    }
+
+    #[test]
+    fn canonical_url() {
+        let actual = Platform::Twitch.url("v1234567890");
+        let expected = Some("https://www.twitch.tv/videos/1234567890".to_owned());
+        assert_eq!(actual, expected);
+        assert_eq!(Platform::Unknown.url("x"), None);
+    }
+}
@@ -0,0 +1,387 @@
+//! Matroska and WebM (EBML) parsing.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use super::{invalid_data, Container, ProbeInfo, Verification};
+
+pub(super) const EBML_MAGIC: [u8; 4] = [0x1A, 0x45, 0xDF, 0xA3];
+
+const ID_EBML: u32 = 0x1A45DFA3;
+const ID_DOC_TYPE: u32 = 0x4282;
+const ID_SEGMENT: u32 = 0x18538067;
+const ID_INFO: u32 = 0x1549A966;
+const ID_TIMESTAMP_SCALE: u32 = 0x2AD7B1;
+const ID_DURATION: u32 = 0x4489;
+const ID_TRACKS: u32 = 0x1654AE6B;
+const ID_TRACK_ENTRY: u32 = 0xAE;
+const ID_TRACK_TYPE: u32 = 0x83;
+const ID_CODEC_ID: u32 = 0x86;
+const ID_VIDEO: u32 = 0xE0;
+const ID_PIXEL_WIDTH: u32 = 0xB0;
+const ID_PIXEL_HEIGHT: u32 = 0xBA;
+
+/// Upper bound of the size of elements read into memory.
+const MAX_ELEMENT_SIZE: u64 = 64 * 1024 * 1024;
+
+pub(super) fn probe<R: Read + Seek>(reader: &mut R) -> io::Result<ProbeInfo> {
+    let doc_type = read_header(reader)?;
+    let container = match doc_type.as_str() {
+        "webm" => Container::WebM,
+        _ => Container::Matroska,
+    };
+    let mut info = ProbeInfo::new(container);
+
+    let segment_end = match read_element_header(reader)? {
+        Some((ID_SEGMENT, size)) => size.map(|size| reader.stream_position().map(|p| p + size)),
+        _ => return Err(invalid_data("no Segment element")),
+    }
+    .transpose()?;
+    let (mut has_info, mut has_tracks) = (false, false);
+    while !(has_info && has_tracks) {
+        if segment_end.is_some_and(|end| reader.stream_position().is_ok_and(|p| p >= end)) {
+            break;
+        }
+        let Some((id, size)) = read_element_header(reader)? else {
+            break;
+        };
+        // Level 1 elements with unknown sizes, typically live-streamed
+        // clusters, cannot be skipped.
+        let Some(size) = size else {
+            break;
+        };
+        match id {
+            ID_INFO => {
+                parse_info(&read_body(reader, size)?, &mut info)?;
+                has_info = true;
+            }
+            ID_TRACKS => {
+                parse_tracks(&read_body(reader, size)?, &mut info)?;
+                has_tracks = true;
+            }
+            _ => {
+                reader.seek(SeekFrom::Current(size as i64))?;
+            }
+        }
+    }
+    Ok(info)
+}
+
+/// Checks the EBML header and that the Segment and its top-level children
+/// exactly cover the file.
+///
+/// Children of unknown size, such as live-streamed clusters, end the check
+/// since their extent cannot be determined without parsing them.
+pub(super) fn verify<R: Read + Seek>(reader: &mut R) -> io::Result<Verification> {
+    let classify = |e: io::Error, what: &str| match e.kind() {
+        io::ErrorKind::UnexpectedEof => Ok(Verification::truncated(format!("incomplete {what}"))),
+        io::ErrorKind::InvalidData => Ok(Verification::corrupt(format!("invalid {what}: {e}"))),
+        _ => Err(e),
+    };
+
+    let len = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+    let doc_type = match read_header(reader) {
+        Ok(doc_type) => doc_type,
+        Err(e) => return classify(e, "EBML header"),
+    };
+    if doc_type != "matroska" && doc_type != "webm" {
+        return Ok(Verification::corrupt(format!(
+            "unexpected DocType {doc_type:?}"
+        )));
+    }
+    let segment_size = match read_element_header(reader) {
+        Ok(Some((ID_SEGMENT, size))) => size,
+        Ok(Some(_)) => return Ok(Verification::corrupt("no Segment element".to_owned())),
+        Ok(None) => return Ok(Verification::truncated("no Segment element".to_owned())),
+        Err(e) => return classify(e, "Segment header"),
+    };
+    let start = reader.stream_position()?;
+    let end = match segment_size {
+        Some(size) if start + size > len => {
+            return Ok(Verification::truncated(format!(
+                "Segment extends {} bytes past the end of the file",
+                start + size - len
+            )));
+        }
+        Some(size) if start + size < len => {
+            return Ok(Verification::corrupt(format!(
+                "{} bytes of trailing data after Segment",
+                len - start - size
+            )));
+        }
+        _ => len,
+    };
+
+    let mut pos = start;
+    while pos < end {
+        let size = match read_element_header(reader) {
+            Ok(Some((_, Some(size)))) => size,
+            Ok(Some((_, None))) => break,
+            Ok(None) => break,
+            Err(e) => return classify(e, &format!("element header at offset {pos}")),
+        };
+        let child_end = reader.stream_position()? + size;
+        if child_end > end {
+            return Ok(Verification::truncated(format!(
+                "element at offset {pos} extends {} bytes past the end of the file",
+                child_end - end
+            )));
+        }
+        pos = reader.seek(SeekFrom::Start(child_end))?;
+    }
+    Ok(Verification::ok())
+}
+
+/// Reads the EBML header and returns the document type.
+pub(super) fn read_header<R: Read>(reader: &mut R) -> io::Result<String> {
+    let size = match read_element_header(reader)? {
+        Some((ID_EBML, Some(size))) => size,
+        _ => return Err(invalid_data("no EBML header")),
+    };
+    let body = read_body(reader, size)?;
+    let doc_type = children(&body)?
+        .into_iter()
+        .find(|(id, _)| *id == ID_DOC_TYPE)
+        .map(|(_, data)| string(data))
+        .unwrap_or_default();
+    Ok(doc_type)
+}
+
+fn read_body<R: Read>(reader: &mut R, size: u64) -> io::Result<Vec<u8>> {
+    if size > MAX_ELEMENT_SIZE {
+        return Err(invalid_data("element too large"));
+    }
+    let mut body = vec![0; size as usize];
+    reader.read_exact(&mut body)?;
+    Ok(body)
+}
+
+/// Reads an element ID and size, returning `None` at the end of the input
+/// and a size of `None` for elements of unknown size.
+pub(super) fn read_element_header<R: Read>(
+    reader: &mut R,
+) -> io::Result<Option<(u32, Option<u64>)>> {
+    let mut first = [0];
+    if reader.read(&mut first)? == 0 {
+        return Ok(None);
+    }
+    let len = first[0].leading_zeros() as usize + 1;
+    if len > 4 {
+        return Err(invalid_data("invalid element ID"));
+    }
+    let mut id = u32::from(first[0]);
+    for b in read_array::<_, 3>(reader, len - 1)?.iter().take(len - 1) {
+        id = id << 8 | u32::from(*b);
+    }
+    let size = read_vint(reader)?;
+    Ok(Some((id, size)))
+}
+
+/// Reads a variable-size integer, returning `None` for the reserved
+/// "unknown" value.
+fn read_vint<R: Read>(reader: &mut R) -> io::Result<Option<u64>> {
+    let mut first = [0];
+    reader.read_exact(&mut first)?;
+    let len = first[0].leading_zeros() as usize + 1;
+    if len > 8 {
+        return Err(invalid_data("invalid element size"));
+    }
+    let mask = 0xFFu8.checked_shr(len as u32).unwrap_or(0);
+    let mut value = u64::from(first[0] & mask);
+    let mut all_ones = value == u64::from(mask);
+    for b in read_array::<_, 7>(reader, len - 1)?.iter().take(len - 1) {
+        value = value << 8 | u64::from(*b);
+        all_ones &= *b == 0xFF;
+    }
+    Ok((!all_ones).then_some(value))
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R, len: usize) -> io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    reader.read_exact(&mut buf[..len])?;
+    Ok(buf)
+}
+
+/// Splits in-memory element data into `(id, body)` pairs of child elements.
+fn children(mut data: &[u8]) -> io::Result<Vec<(u32, &[u8])>> {
+    let mut elements = Vec::new();
+    while !data.is_empty() {
+        let mut reader = data;
+        let (id, size) = match read_element_header(&mut reader) {
+            Ok(Some((id, Some(size)))) if size <= reader.len() as u64 => (id, size as usize),
+            _ => return Err(invalid_data("broken element header")),
+        };
+        elements.push((id, &reader[..size]));
+        data = &reader[size..];
+    }
+    Ok(elements)
+}
+
+fn uint(data: &[u8]) -> u64 {
+    data.iter().fold(0, |acc, b| acc << 8 | u64::from(*b))
+}
+
+fn float(data: &[u8]) -> Option<f64> {
+    match data.len() {
+        4 => Some(f64::from(f32::from_be_bytes(data.try_into().unwrap()))),
+        8 => Some(f64::from_be_bytes(data.try_into().unwrap())),
+        _ => None,
+    }
+}
+
+fn string(data: &[u8]) -> String {
+    let data = data.split(|b| *b == 0).next().unwrap_or_default();
+    String::from_utf8_lossy(data).into_owned()
+}
+
+fn parse_info(data: &[u8], info: &mut ProbeInfo) -> io::Result<()> {
+    let mut scale = 1_000_000;
+    let mut duration = None;
+    for (id, body) in children(data)? {
+        match id {
+            ID_TIMESTAMP_SCALE => scale = uint(body),
+            ID_DURATION => duration = float(body),
+            _ => {}
+        }
+    }
+    info.duration = duration.map(|d| d * scale as f64 / 1e9);
+    Ok(())
+}
+
+fn parse_tracks(data: &[u8], info: &mut ProbeInfo) -> io::Result<()> {
+    for (id, entry) in children(data)? {
+        if id != ID_TRACK_ENTRY {
+            continue;
+        }
+        info.tracks += 1;
+        let (mut kind, mut codec, mut width, mut height) = (0, None, None, None);
+        for (id, body) in children(entry)? {
+            match id {
+                ID_TRACK_TYPE => kind = uint(body),
+                ID_CODEC_ID => codec = Some(string(body)),
+                ID_VIDEO => {
+                    for (id, body) in children(body)? {
+                        match id {
+                            ID_PIXEL_WIDTH => width = Some(uint(body) as u32),
+                            ID_PIXEL_HEIGHT => height = Some(uint(body) as u32),
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        match kind {
+            1 if info.video_codec.is_none() => {
+                info.video_codec = codec;
+                info.width = width;
+                info.height = height;
+            }
+            2 if info.audio_codec.is_none() => info.audio_codec = codec,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::Integrity;
+
+    const ID_CLUSTER: u32 = 0x1F43B675;
+
+    pub(crate) fn element(id: u32, body: &[u8]) -> Vec<u8> {
+        let id = id.to_be_bytes();
+        let skip = id.iter().take_while(|b| **b == 0).count();
+        let mut data = id[skip..].to_vec();
+        data.extend_from_slice(&(0x0100_0000_0000_0000 | body.len() as u64).to_be_bytes());
+        data.extend_from_slice(body);
+        data
+    }
+
+    /// Returns a minimal WebM file of 12.5 seconds with a VP9 video track and
+    /// an Opus audio track.
+    pub(crate) fn sample_webm() -> Vec<u8> {
+        let header = element(ID_EBML, &element(ID_DOC_TYPE, b"webm"));
+        let info = element(
+            ID_INFO,
+            &[
+                element(ID_TIMESTAMP_SCALE, &[0x0F, 0x42, 0x40]),
+                element(ID_DURATION, &12500f64.to_be_bytes()),
+            ]
+            .concat(),
+        );
+        let video = element(
+            ID_VIDEO,
+            &[
+                element(ID_PIXEL_WIDTH, &[0x05, 0x00]),
+                element(ID_PIXEL_HEIGHT, &[0x02, 0xD0]),
+            ]
+            .concat(),
+        );
+        let video = [
+            element(ID_TRACK_TYPE, &[1]),
+            element(ID_CODEC_ID, b"V_VP9"),
+            video,
+        ];
+        let audio = [
+            element(ID_TRACK_TYPE, &[2]),
+            element(ID_CODEC_ID, b"A_OPUS"),
+        ];
+        let tracks = element(
+            ID_TRACKS,
+            &[
+                element(ID_TRACK_ENTRY, &video.concat()),
+                element(ID_TRACK_ENTRY, &audio.concat()),
+            ]
+            .concat(),
+        );
+        let cluster = element(ID_CLUSTER, &[0; 16]);
+        let segment = element(ID_SEGMENT, &[info, tracks, cluster].concat());
+        [header, segment].concat()
+    }
+
+    #[test]
+    fn probing() {
+        let actual = ProbeInfo::from_reader(&mut Cursor::new(sample_webm())).unwrap();
+        let actual = actual.unwrap();
+        let expected = ProbeInfo {
+            container: Container::WebM,
+            duration: Some(12.5),
+            width: Some(1280),
+            height: Some(720),
+            video_codec: Some("V_VP9".to_owned()),
+            audio_codec: Some("A_OPUS".to_owned()),
+            tracks: 2,
+        };
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn verification() {
+        let verify = |data: Vec<u8>| Verification::from_reader(&mut Cursor::new(data)).unwrap();
+
+        assert_eq!(verify(sample_webm()), Verification::ok());
+
+        let mut data = sample_webm();
+        data.truncate(data.len() - 4);
+        let expected =
+            Verification::truncated("Segment extends 4 bytes past the end of the file".to_owned());
+        assert_eq!(verify(data), expected);
+
+        let mut data = sample_webm();
+        data[5] = 0x42;
+        assert_eq!(verify(data).status, Integrity::Corrupt);
+    }
+
+    #[test]
+    fn vints() {
+        let mut data: &[u8] = &[0x81, 0x40, 0x02, 0xFF];
+        assert_eq!(read_vint(&mut data).unwrap(), Some(1));
+        assert_eq!(read_vint(&mut data).unwrap(), Some(2));
+        assert_eq!(read_vint(&mut data).unwrap(), None);
+    }
+}
@@ -0,0 +1,322 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+mod mkv;
+mod mp4;
+
+#[cfg(test)]
+pub(crate) use mp4::tests::sample_mp4;
+
+/// Length of MPEG transport stream packets.
+const TS_PACKET_LEN: usize = 188;
+
+/// Container format of a movie file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Container {
+    Mp4,
+    Matroska,
+    WebM,
+    Avi,
+    Flv,
+    MpegTs,
+    /// Ogg, typically holding Opus or Vorbis audio.
+    Ogg,
+    Mp3,
+}
+
+impl Container {
+    /// Recognizes the container of a file by its first bytes, or returns
+    /// `None` if it is not a movie or audio file.
+    pub fn sniff_path<P: AsRef<Path>>(path: P) -> io::Result<Option<Self>> {
+        let mut reader = BufReader::new(File::open(path)?);
+        Self::sniff(&mut reader)
+    }
+
+    /// Recognizes an MP4 `ftyp` box, an EBML header, a RIFF AVI header, an
+    /// FLV signature, MPEG transport stream sync bytes, an Ogg page, or an
+    /// ID3 tag or MPEG audio layer III frame.
+    pub fn sniff<R: Read + Seek>(reader: &mut R) -> io::Result<Option<Self>> {
+        let mut magic = [0; TS_PACKET_LEN + 1];
+        let n = read_up_to(reader, &mut magic)?;
+        let magic = &magic[..n];
+        let container = if magic.starts_with(&mkv::EBML_MAGIC) {
+            reader.seek(SeekFrom::Start(0))?;
+            // A header too broken to read the document type from is still
+            // taken as Matroska, to be reported by probing or verification.
+            match mkv::read_header(reader) {
+                Ok(doc_type) if doc_type == "webm" => Self::WebM,
+                _ => Self::Matroska,
+            }
+        } else if magic.get(4..8) == Some(b"ftyp") {
+            Self::Mp4
+        } else if magic.starts_with(b"RIFF") && magic.get(8..12) == Some(b"AVI ") {
+            Self::Avi
+        } else if magic.starts_with(b"FLV\x01") {
+            Self::Flv
+        } else if magic.starts_with(b"OggS") {
+            Self::Ogg
+        } else if magic.first() == Some(&0x47) && magic.get(TS_PACKET_LEN) == Some(&0x47) {
+            Self::MpegTs
+        } else if magic.starts_with(b"ID3") || is_mp3_frame(magic) {
+            Self::Mp3
+        } else {
+            return Ok(None);
+        };
+        Ok(Some(container))
+    }
+
+    /// Returns the name of the container as serialized, such as `mp4`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Mp4 => "mp4",
+            Self::Matroska => "matroska",
+            Self::WebM => "webm",
+            Self::Avi => "avi",
+            Self::Flv => "flv",
+            Self::MpegTs => "mpegts",
+            Self::Ogg => "ogg",
+            Self::Mp3 => "mp3",
+        }
+    }
+}
+
+/// Returns `true` if the bytes start with the frame sync of an MPEG audio
+/// layer III frame.
+fn is_mp3_frame(bytes: &[u8]) -> bool {
+    matches!(bytes, [0xFF, b, ..] if b & 0xE0 == 0xE0 && b & 0x06 == 0x02)
+}
+
+/// Stream information read from the container of a movie file.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ProbeInfo {
+    pub container: Container,
+    /// Duration in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    /// Codec ID of the first video track, such as `avc1` or `V_VP9`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub video_codec: Option<String>,
+    /// Codec ID of the first audio track, such as `mp4a` or `A_OPUS`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio_codec: Option<String>,
+    pub tracks: usize,
+}
+
+impl ProbeInfo {
+    fn new(container: Container) -> Self {
+        Self {
+            container,
+            duration: None,
+            width: None,
+            height: None,
+            video_codec: None,
+            audio_codec: None,
+            tracks: 0,
+        }
+    }
+
+    /// Reads stream information from the MP4 `moov` box or the Matroska
+    /// `Info` and `Tracks` elements of a file, or returns `None` for files
+    /// in other containers, which are not parsed, and files in no container
+    /// at all, which verification reports.
+    ///
+    /// The container is recognized by content, not by extension. Errors of
+    /// kind [`io::ErrorKind::InvalidData`] mean that the structure of the
+    /// container is broken.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> io::Result<Option<Self>> {
+        let mut reader = BufReader::new(File::open(path)?);
+        Self::from_reader(&mut reader)
+    }
+
+    pub fn from_reader<R: Read + Seek>(reader: &mut R) -> io::Result<Option<Self>> {
+        let mut magic = [0; 8];
+        let n = read_up_to(reader, &mut magic)?;
+        reader.seek(SeekFrom::Start(0))?;
+        if n >= 4 && magic[..4] == mkv::EBML_MAGIC {
+            mkv::probe(reader).map(Some)
+        } else if n >= 8 && &magic[4..8] == b"ftyp" {
+            mp4::probe(reader).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Structural integrity of a movie file container.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Integrity {
+    Ok,
+    /// The file ends before the end of the container structure, typically
+    /// because a download was interrupted.
+    Truncated,
+    /// The container structure is broken or missing essential parts.
+    Corrupt,
+    /// The container is recognized but its structure is not checked, which
+    /// is only done for MP4 and Matroska/WebM.
+    Unchecked,
+}
+
+/// Result of checking the container structure of a movie file.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Verification {
+    pub status: Integrity,
+    /// Description of the first problem found.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl Verification {
+    pub fn ok() -> Self {
+        Self {
+            status: Integrity::Ok,
+            reason: None,
+        }
+    }
+
+    pub fn truncated(reason: String) -> Self {
+        Self {
+            status: Integrity::Truncated,
+            reason: Some(reason),
+        }
+    }
+
+    pub fn corrupt(reason: String) -> Self {
+        Self {
+            status: Integrity::Corrupt,
+            reason: Some(reason),
+        }
+    }
+
+    pub fn unchecked(reason: String) -> Self {
+        Self {
+            status: Integrity::Unchecked,
+            reason: Some(reason),
+        }
+    }
+
+    /// Checks the container structure of a file.
+    ///
+    /// For MP4, top-level box sizes must add up to the file length and a
+    /// `moov` box must be present. For Matroska and WebM, the EBML header
+    /// must be valid and the Segment size must match the file length. Other
+    /// containers recognized by [`Container::sniff`] are
+    /// [`Integrity::Unchecked`].
+    pub fn from_path<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        Self::from_reader(&mut reader)
+    }
+
+    pub fn from_reader<R: Read + Seek>(reader: &mut R) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        let n = read_up_to(reader, &mut magic)?;
+        reader.seek(SeekFrom::Start(0))?;
+        if n == 0 {
+            Ok(Self::truncated("empty file".to_owned()))
+        } else if n >= 4 && magic[..4] == mkv::EBML_MAGIC {
+            mkv::verify(reader)
+        } else if n >= 8 && &magic[4..8] == b"ftyp" {
+            mp4::verify(reader)
+        } else {
+            match Container::sniff(reader)? {
+                Some(container) => Ok(Self::unchecked(format!(
+                    "{} containers are not checked",
+                    container.name()
+                ))),
+                None => Ok(Self::corrupt("unrecognized container".to_owned())),
+            }
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.status == Integrity::Ok
+    }
+
+    /// Returns `true` if the file is known to be truncated or corrupt.
+    pub fn is_failure(&self) -> bool {
+        matches!(self.status, Integrity::Truncated | Integrity::Corrupt)
+    }
+}
+
+/// Reads into `buf` until it is full or the end of the input is reached,
+/// returning the number of bytes read.
+fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match reader.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(m) => n += m,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Cursor};
+
+    use super::*;
+    use crate::{testing::TempDir, Scanner};
+
+    #[test]
+    fn sniffing() {
+        let mut ts = vec![0; TS_PACKET_LEN * 2];
+        ts[0] = 0x47;
+        ts[TS_PACKET_LEN] = 0x47;
+        let cases: [(&[u8], Option<Container>); 9] = [
+            (&sample_mp4(), Some(Container::Mp4)),
+            (b"RIFF\0\0\0\0AVI LIST", Some(Container::Avi)),
+            (b"RIFF\0\0\0\0WAVEfmt ", None),
+            (b"FLV\x01\x05", Some(Container::Flv)),
+            (&ts, Some(Container::MpegTs)),
+            (b"OggS\0\x02", Some(Container::Ogg)),
+            (b"\xFF\xFB\x90\x64", Some(Container::Mp3)),
+            (b"\xFF\xD8\xFF\xE0 JFIF", None),
+            (b"", None),
+        ];
+        for (bytes, expected) in cases {
+            let actual = Container::sniff(&mut Cursor::new(bytes)).unwrap();
+            assert_eq!(actual, expected, "{:?}", &bytes[..bytes.len().min(8)]);
+        }
+    }
+
+    #[test]
+    fn containers_that_are_not_parsed() {
+        let dir = TempDir::new("probe");
+        let mut ts = vec![0; TS_PACKET_LEN * 2];
+        ts[0] = 0x47;
+        ts[TS_PACKET_LEN] = 0x47;
+        let cases: [(&str, &[u8], Integrity); 3] = [
+            ("Clip [a].ts", &ts, Integrity::Unchecked),
+            (
+                "Clip [b].avi",
+                b"RIFF\0\0\0\0AVI LIST",
+                Integrity::Unchecked,
+            ),
+            ("Clip [c].avi", b"not a movie", Integrity::Corrupt),
+        ];
+        let scanner = Scanner::new().verify(true).probe(true);
+        for (name, bytes, expected) in cases {
+            let path = dir.join("@u").join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, bytes).unwrap();
+            let entry = scanner.process(&path).unwrap();
+            assert_eq!(entry.probe, None, "{name}");
+            assert_eq!(entry.integrity.unwrap().status, expected, "{name}");
+        }
+    }
+}
@@ -0,0 +1,291 @@
+//! ISO base media file format (MP4) parsing.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use super::{invalid_data, Container, ProbeInfo, Verification};
+
+/// Upper bound of the `moov` box size read into memory.
+const MAX_MOOV_SIZE: u64 = 256 * 1024 * 1024;
+
+pub(super) fn probe<R: Read + Seek>(reader: &mut R) -> io::Result<ProbeInfo> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    let mut pos = reader.seek(SeekFrom::Start(0))?;
+    while pos < len {
+        let (kind, header_len, size) = read_box_header(reader, len - pos)?;
+        if size > len - pos {
+            return Err(invalid_data("box extends past the end of the file"));
+        }
+        if &kind == b"moov" {
+            let body_len = size - header_len;
+            if body_len > MAX_MOOV_SIZE {
+                return Err(invalid_data("moov box too large"));
+            }
+            let mut body = vec![0; body_len as usize];
+            reader.read_exact(&mut body)?;
+            return parse_moov(&body);
+        }
+        pos = reader.seek(SeekFrom::Start(pos + size))?;
+    }
+    Err(invalid_data("no moov box"))
+}
+
+/// Checks that top-level boxes start with `ftyp`, include `moov` and exactly
+/// cover the file.
+pub(super) fn verify<R: Read + Seek>(reader: &mut R) -> io::Result<Verification> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    let mut pos = reader.seek(SeekFrom::Start(0))?;
+    let mut has_moov = false;
+    while pos < len {
+        let (kind, _, size) = match read_box_header(reader, len - pos) {
+            Ok(header) => header,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Ok(Verification::truncated(format!(
+                    "incomplete box header at offset {pos}"
+                )));
+            }
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                return Ok(Verification::corrupt(format!(
+                    "invalid box size at offset {pos}"
+                )));
+            }
+            Err(e) => return Err(e),
+        };
+        let name = String::from_utf8_lossy(&kind);
+        if !kind.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+            return Ok(Verification::corrupt(format!(
+                "invalid box type at offset {pos}"
+            )));
+        }
+        if pos == 0 && &kind != b"ftyp" {
+            return Ok(Verification::corrupt(format!(
+                "file starts with {name:?} box instead of \"ftyp\""
+            )));
+        }
+        if size > len - pos {
+            return Ok(Verification::truncated(format!(
+                "{name:?} box at offset {pos} extends {} bytes past the end of the file",
+                size - (len - pos)
+            )));
+        }
+        has_moov |= &kind == b"moov";
+        pos = reader.seek(SeekFrom::Start(pos + size))?;
+    }
+    if !has_moov {
+        return Ok(Verification::corrupt("no moov box".to_owned()));
+    }
+    Ok(Verification::ok())
+}
+
+/// Reads a box header at the current position, returning the box type, the
+/// header length and the total box size.
+///
+/// `remaining` is the number of bytes from the current position to the end
+/// of the enclosing data, which is used for boxes extending to the end. The
+/// size is not checked against it.
+pub(super) fn read_box_header<R: Read>(
+    reader: &mut R,
+    remaining: u64,
+) -> io::Result<([u8; 4], u64, u64)> {
+    let mut header = [0; 8];
+    reader.read_exact(&mut header)?;
+    let kind = header[4..8].try_into().unwrap();
+    let (header_len, size) = match u32::from_be_bytes(header[..4].try_into().unwrap()) {
+        0 => (8, remaining),
+        1 => {
+            let mut large = [0; 8];
+            reader.read_exact(&mut large)?;
+            (16, u64::from_be_bytes(large))
+        }
+        size => (8, u64::from(size)),
+    };
+    if size < header_len {
+        return Err(invalid_data("box size out of range"));
+    }
+    Ok((kind, header_len, size))
+}
+
+/// Splits in-memory box data into `(type, body)` pairs of child boxes.
+fn children(mut data: &[u8]) -> io::Result<Vec<([u8; 4], &[u8])>> {
+    let mut boxes = Vec::new();
+    while !data.is_empty() {
+        let mut reader = data;
+        let (kind, header_len, size) = read_box_header(&mut reader, data.len() as u64)
+            .ok()
+            .filter(|(_, _, size)| *size <= data.len() as u64)
+            .ok_or_else(|| invalid_data("broken box header"))?;
+        boxes.push((kind, &data[header_len as usize..size as usize]));
+        data = &data[size as usize..];
+    }
+    Ok(boxes)
+}
+
+fn child<'a>(data: &'a [u8], kind: &[u8; 4]) -> io::Result<Option<&'a [u8]>> {
+    let found = children(data)?.into_iter().find(|(k, _)| k == kind);
+    Ok(found.map(|(_, body)| body))
+}
+
+fn bytes<const N: usize>(data: &[u8], offset: usize) -> io::Result<[u8; N]> {
+    data.get(offset..offset + N)
+        .map(|b| b.try_into().unwrap())
+        .ok_or_else(|| invalid_data("box too short"))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> io::Result<u32> {
+    bytes(data, offset).map(u32::from_be_bytes)
+}
+
+fn u64_at(data: &[u8], offset: usize) -> io::Result<u64> {
+    bytes(data, offset).map(u64::from_be_bytes)
+}
+
+fn parse_moov(moov: &[u8]) -> io::Result<ProbeInfo> {
+    let mut info = ProbeInfo::new(Container::Mp4);
+    for (kind, body) in children(moov)? {
+        match &kind {
+            b"mvhd" => info.duration = parse_mvhd(body)?,
+            b"trak" => {
+                info.tracks += 1;
+                parse_trak(body, &mut info)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(info)
+}
+
+fn parse_mvhd(mvhd: &[u8]) -> io::Result<Option<f64>> {
+    let (timescale, duration) = match mvhd.first() {
+        Some(1) => (u32_at(mvhd, 20)?, u64_at(mvhd, 24)?),
+        Some(_) => (u32_at(mvhd, 12)?, u64::from(u32_at(mvhd, 16)?)),
+        None => return Err(invalid_data("empty mvhd box")),
+    };
+    // Fragmented files have zero durations in `mvhd`.
+    Ok((timescale != 0 && duration != 0).then(|| duration as f64 / f64::from(timescale)))
+}
+
+fn parse_trak(trak: &[u8], info: &mut ProbeInfo) -> io::Result<()> {
+    let Some(mdia) = child(trak, b"mdia")? else {
+        return Ok(());
+    };
+    let handler = match child(mdia, b"hdlr")? {
+        Some(hdlr) => bytes::<4>(hdlr, 8)?,
+        None => return Ok(()),
+    };
+    let codec = match child(mdia, b"minf")? {
+        Some(minf) => match child(minf, b"stbl")? {
+            Some(stbl) => match child(stbl, b"stsd")? {
+                Some(stsd) if u32_at(stsd, 4)? > 0 => {
+                    Some(String::from_utf8_lossy(&bytes::<4>(stsd, 12)?).into_owned())
+                }
+                _ => None,
+            },
+            None => None,
+        },
+        None => None,
+    };
+    match &handler {
+        b"vide" if info.video_codec.is_none() => {
+            info.video_codec = codec;
+            if let Some(tkhd) = child(trak, b"tkhd")? {
+                let offset = if tkhd.first() == Some(&1) { 88 } else { 76 };
+                info.width = Some(u32_at(tkhd, offset)? >> 16);
+                info.height = Some(u32_at(tkhd, offset + 4)? >> 16);
+            }
+        }
+        b"soun" if info.audio_codec.is_none() => info.audio_codec = codec,
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    pub(crate) fn mp4_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut data = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(body);
+        data
+    }
+
+    fn trak(handler: &[u8; 4], codec: &[u8; 4], width: u32, height: u32) -> Vec<u8> {
+        let mut tkhd = vec![0; 84];
+        tkhd[76..80].copy_from_slice(&(width << 16).to_be_bytes());
+        tkhd[80..84].copy_from_slice(&(height << 16).to_be_bytes());
+        let mut hdlr = vec![0; 12];
+        hdlr[8..12].copy_from_slice(handler);
+        let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        stsd.extend(mp4_box(codec, &[0; 8]));
+        let stbl = mp4_box(b"stbl", &mp4_box(b"stsd", &stsd));
+        let minf = mp4_box(b"minf", &stbl);
+        let mdia = mp4_box(b"mdia", &[mp4_box(b"hdlr", &hdlr), minf].concat());
+        mp4_box(b"trak", &[mp4_box(b"tkhd", &tkhd), mdia].concat())
+    }
+
+    /// Returns a minimal MP4 file of 12.5 seconds with an H.264 video track
+    /// and an AAC audio track.
+    pub(crate) fn sample_mp4() -> Vec<u8> {
+        let mut mvhd = vec![0; 20];
+        mvhd[12..16].copy_from_slice(&1000u32.to_be_bytes());
+        mvhd[16..20].copy_from_slice(&12500u32.to_be_bytes());
+        let moov = [
+            mp4_box(b"mvhd", &mvhd),
+            trak(b"vide", b"avc1", 1920, 1080),
+            trak(b"soun", b"mp4a", 0, 0),
+        ]
+        .concat();
+        [
+            mp4_box(b"ftyp", b"isom\0\0\0\0"),
+            mp4_box(b"moov", &moov),
+            mp4_box(b"mdat", &[0; 16]),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn probing() {
+        let actual = ProbeInfo::from_reader(&mut Cursor::new(sample_mp4())).unwrap();
+        let actual = actual.unwrap();
+        let expected = ProbeInfo {
+            container: Container::Mp4,
+            duration: Some(12.5),
+            width: Some(1920),
+            height: Some(1080),
+            video_codec: Some("avc1".to_owned()),
+            audio_codec: Some("mp4a".to_owned()),
+            tracks: 2,
+        };
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn probing_without_moov() {
+        let data = [mp4_box(b"ftyp", b"isom"), mp4_box(b"mdat", &[0; 16])].concat();
+        let actual = ProbeInfo::from_reader(&mut Cursor::new(data));
+        assert_eq!(actual.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn verification() {
+        let verify = |data: Vec<u8>| Verification::from_reader(&mut Cursor::new(data)).unwrap();
+
+        assert_eq!(verify(sample_mp4()), Verification::ok());
+
+        let mut data = sample_mp4();
+        let mdat_offset = data.len() - 24;
+        data.truncate(data.len() - 4);
+        let expected = Verification::truncated(format!(
+            "\"mdat\" box at offset {mdat_offset} extends 4 bytes past the end of the file"
+        ));
+        assert_eq!(verify(data), expected);
+
+        let data = [mp4_box(b"ftyp", b"isom"), mp4_box(b"mdat", &[0; 16])].concat();
+        assert_eq!(
+            verify(data),
+            Verification::corrupt("no moov box".to_owned())
+        );
+    }
+}
@@ -0,0 +1,768 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{mpsc, Mutex},
+    thread, vec,
+};
+
+use crate::{
+    cache::{self, CacheState, CachedChild, Stamp},
+    extensions,
+    filter::Ignores,
+    walk, Container, Error, Extensions, FileInfo, Filter, InfoJson, LegacyEncoding, MovieEntry,
+    ParseOptions, ProbeInfo, ScanCache, Template, UserRule, Verification, IGNORE_FILE,
+};
+
+/// Builder for scans of directory trees containing movie files.
+///
+/// ```no_run
+/// use lsmovie::Scanner;
+///
+/// for result in Scanner::new().scan(["/srv/movies"]) {
+///     match result {
+///         Ok(entry) => println!("{} {}", entry.id, entry.title),
+///         Err(e) => eprintln!("{e}"),
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Scanner {
+    info_json: bool,
+    extensions: Extensions,
+    sniff: bool,
+    templates: Vec<Template>,
+    user_rules: Vec<UserRule>,
+    legacy_encodings: Vec<LegacyEncoding>,
+    original_names: bool,
+    restore_title: bool,
+    strip_date: bool,
+    probe: bool,
+    verify: bool,
+    file_info: bool,
+    jobs: usize,
+    sort: bool,
+    follow_symlinks: bool,
+    same_file_system: bool,
+    filter: Filter,
+    ignore_files: bool,
+    skip_hidden: bool,
+}
+
+impl Default for Scanner {
+    fn default() -> Self {
+        Self {
+            info_json: true,
+            extensions: Extensions::default(),
+            sniff: false,
+            templates: vec![Template::bracket()],
+            user_rules: vec![UserRule::default()],
+            legacy_encodings: Vec::new(),
+            original_names: false,
+            restore_title: false,
+            strip_date: false,
+            probe: false,
+            verify: false,
+            file_info: false,
+            jobs: 1,
+            sort: false,
+            follow_symlinks: true,
+            same_file_system: false,
+            filter: Filter::default(),
+            ignore_files: true,
+            skip_hidden: false,
+        }
+    }
+}
+
+impl Scanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether entries are enriched with yt-dlp `.info.json` sidecars
+    /// found next to movie files. Enabled by default.
+    pub fn info_json(mut self, yes: bool) -> Self {
+        self.info_json = yes;
+        self
+    }
+
+    /// Sets the extensions of the files to list. Defaults to the `video`
+    /// preset of [`Extensions`].
+    pub fn extensions(mut self, extensions: Extensions) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
+    /// Sets whether the container of every file is recognized by content,
+    /// so that files with other extensions are listed if they are movies
+    /// and files with movie extensions are reported as
+    /// [`Error::UnrecognizedContent`] if they are not. Files without an
+    /// extension are always checked. Disabled by default.
+    pub fn sniff(mut self, yes: bool) -> Self {
+        self.sniff = yes;
+        self
+    }
+
+    /// Sets the filename templates tried in order when parsing file stems.
+    /// Defaults to [`Template::bracket`] only.
+    pub fn templates(mut self, templates: Vec<Template>) -> Self {
+        self.templates = templates;
+        self
+    }
+
+    /// Sets the rules tried in order to find the user of a movie. Defaults
+    /// to `prefix=@` only; see [`UserRule`].
+    pub fn user_rules(mut self, rules: Vec<UserRule>) -> Self {
+        self.user_rules = rules;
+        self
+    }
+
+    /// Sets the encodings tried in order to decode paths that are not valid
+    /// UTF-8 before parsing them; see
+    /// [`ParseOptions::encodings`]. None by default.
+    pub fn legacy_encodings(mut self, encodings: Vec<LegacyEncoding>) -> Self {
+        self.legacy_encodings = encodings;
+        self
+    }
+
+    /// Sets whether entries keep the title and user found in the path in
+    /// `original_title` and `original_user` when they are not in NFC; see
+    /// [`ParseOptions::original_names`]. Disabled by default.
+    pub fn original_names(mut self, yes: bool) -> Self {
+        self.original_names = yes;
+        self
+    }
+
+    /// Sets whether entries get the original title of the video, from the
+    /// `.info.json` sidecar if read or else by reversing the substitutions
+    /// yt-dlp makes in file names; see [`MovieEntry::restore_title`].
+    /// Disabled by default.
+    pub fn restore_title(mut self, yes: bool) -> Self {
+        self.restore_title = yes;
+        self
+    }
+
+    /// Sets whether entries get a `title_without_date` with the date found
+    /// in the title removed. Disabled by default.
+    pub fn strip_date(mut self, yes: bool) -> Self {
+        self.strip_date = yes;
+        self
+    }
+
+    /// Sets whether movie containers are parsed for stream information.
+    /// Disabled by default since it requires reading every file.
+    pub fn probe(mut self, yes: bool) -> Self {
+        self.probe = yes;
+        self
+    }
+
+    /// Sets whether the container structure of movie files is checked for
+    /// truncation and corruption. Disabled by default since it requires
+    /// reading every file.
+    pub fn verify(mut self, yes: bool) -> Self {
+        self.verify = yes;
+        self
+    }
+
+    /// Sets whether entries get [`FileInfo`] such as absolute paths, sizes
+    /// and timestamps. Disabled by default.
+    pub fn file_info(mut self, yes: bool) -> Self {
+        self.file_info = yes;
+        self
+    }
+
+    /// Sets the number of threads traversing directories and processing
+    /// files. With more than one, directories are handed out to idle
+    /// threads, which helps when file system latency dominates, and results
+    /// are yielded in the order they are ready. Defaults to 1.
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// Sets whether results are yielded in path order rather than in the
+    /// order they are found, making the output deterministic at the cost of
+    /// finishing the scan before the first result. Disabled by default.
+    pub fn sort(mut self, yes: bool) -> Self {
+        self.sort = yes;
+        self
+    }
+
+    /// Sets whether symbolic links to directories are descended into.
+    /// Symbolic links to files are processed either way, and links leading
+    /// back to a directory being traversed are reported as
+    /// [`Error::SymlinkLoop`]. Enabled by default.
+    pub fn follow_symlinks(mut self, yes: bool) -> Self {
+        self.follow_symlinks = yes;
+        self
+    }
+
+    /// Sets whether directories on other file systems than their root are
+    /// skipped. Disabled by default.
+    pub fn same_file_system(mut self, yes: bool) -> Self {
+        self.same_file_system = yes;
+        self
+    }
+
+    /// Sets the patterns selecting the files and directories to scan.
+    /// Everything is selected by default.
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Sets whether files and directories matching the patterns in
+    /// [`IGNORE_FILE`](crate::IGNORE_FILE)s of the root directories and
+    /// their subdirectories are skipped. Enabled by default.
+    pub fn ignore_files(mut self, yes: bool) -> Self {
+        self.ignore_files = yes;
+        self
+    }
+
+    /// Sets whether files and directories with names starting with `.` are
+    /// skipped. Disabled by default.
+    pub fn skip_hidden(mut self, yes: bool) -> Self {
+        self.skip_hidden = yes;
+        self
+    }
+
+    /// Returns a description of the settings entries depend on.
+    fn settings(&self) -> String {
+        format!(
+            "{:?}",
+            (
+                self.info_json,
+                &self.extensions,
+                self.sniff,
+                &self.templates,
+                &self.user_rules,
+                &self.legacy_encodings,
+                self.original_names,
+                self.restore_title,
+                self.strip_date,
+                self.probe,
+                self.verify,
+                self.file_info,
+            )
+        )
+    }
+
+    /// Starts a scan of the given root directories, which are traversed in
+    /// order.
+    pub fn scan<I, P>(&self, roots: I) -> Scan
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let roots = roots
+            .into_iter()
+            .map(|root| root.as_ref().to_path_buf())
+            .collect::<Vec<_>>();
+        Scan {
+            scanner: self.clone(),
+            roots: roots.into_iter(),
+            scanned: Vec::new(),
+            root: PathBuf::new(),
+            stack: Vec::new(),
+            cache: None,
+            parallel: None,
+            sorted: None,
+        }
+    }
+
+    /// Extracts movie information from a single file path.
+    pub fn process<P: AsRef<Path>>(&self, path: P) -> Result<MovieEntry, Error> {
+        self.process_in(path.as_ref(), None)
+    }
+
+    /// Resolves a child of a directory being traversed under `root`, with
+    /// the ignore files in effect in the directory, returning whether it is
+    /// a directory to descend into, or `None` if it is skipped.
+    pub(crate) fn resolve(
+        &self,
+        path: &Path,
+        kind: EntryKind,
+        root: &Path,
+        ignores: &Ignores,
+    ) -> Result<Option<bool>, Error> {
+        let hidden = path
+            .file_name()
+            .is_some_and(|name| name.as_encoded_bytes().starts_with(b"."));
+        if self.skip_hidden && hidden {
+            return Ok(None);
+        }
+        // Dangling symbolic links are only reported if they are selected.
+        let (is_dir, dangling) = match kind {
+            EntryKind::File => (false, None),
+            EntryKind::Dir => (true, None),
+            EntryKind::Symlink => match fs::metadata(path) {
+                Ok(metadata) => (metadata.is_dir(), None),
+                Err(source) => (false, Some(source)),
+            },
+        };
+        // Sidecars and ignore files are read along with the files and
+        // directories they belong to rather than reported as unsupported.
+        let control =
+            path.file_name().is_some_and(|name| name == IGNORE_FILE) || InfoJson::is_sidecar(path);
+        if control && !is_dir {
+            return Ok(None);
+        }
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        if !self.filter.selects(relative, is_dir) || ignores.ignores(path, is_dir) {
+            return Ok(None);
+        }
+        if let Some(source) = dangling {
+            let path = path.to_path_buf();
+            return Err(Error::DanglingSymlink { path, source });
+        }
+        if is_dir && kind == EntryKind::Symlink && !self.follow_symlinks {
+            return Ok(None);
+        }
+        Ok(Some(is_dir))
+    }
+
+    /// Returns the ignore files in effect in `dir`, given those in effect in
+    /// its parent, along with the error reading its own ignore file, if any.
+    pub(crate) fn ignores(&self, dir: &Path, parent: &Ignores) -> (Ignores, Option<Error>) {
+        if !self.ignore_files {
+            return (Ignores::default(), None);
+        }
+        match parent.enter(dir) {
+            Ok(ignores) => (ignores, None),
+            Err(e) => (parent.clone(), Some(e)),
+        }
+    }
+
+    /// Checks a directory before entering it, given the directories it was
+    /// reached through starting with its root, returning `None` if it is
+    /// skipped for being on another file system.
+    pub(crate) fn check_dir(
+        &self,
+        dir: &Path,
+        ancestors: &[DirId],
+    ) -> Result<Option<Entering>, Error> {
+        let metadata = fs::metadata(dir).map_err(|source| Error::UnreadableDir {
+            path: dir.to_path_buf(),
+            source,
+        })?;
+        let id = dir_id(&metadata);
+        if let (Some(id), Some(root)) = (id, ancestors.first()) {
+            if self.same_file_system && id.0 != root.0 {
+                return Ok(None);
+            }
+            if ancestors.contains(&id) {
+                return Err(Error::SymlinkLoop(dir.to_path_buf()));
+            }
+        }
+        let mtime = cache::mtime(&metadata);
+        Ok(Some(Entering { id, mtime }))
+    }
+
+    /// Extracts movie information from the path alone, without reading the
+    /// file or its sidecar, taking `uploader` from the sidecar if read.
+    pub(crate) fn parse(
+        &self,
+        path: &Path,
+        root: Option<&Path>,
+        uploader: Option<&str>,
+    ) -> Result<MovieEntry, Error> {
+        if !self.is_supported(path) {
+            return Err(Error::UnsupportedExtension(path.to_path_buf()));
+        }
+        let options = ParseOptions {
+            templates: &self.templates,
+            encodings: &self.legacy_encodings,
+            user_rules: &self.user_rules,
+            root,
+            uploader,
+            normalize: true,
+            original_names: self.original_names,
+        };
+        MovieEntry::from_path_with(path, &options)
+    }
+
+    /// Returns `false` for files that are not movies judging by their
+    /// extension alone.
+    fn is_supported(&self, path: &Path) -> bool {
+        extensions::extension(path).is_none_or(|ext| self.sniff || self.extensions.contains(ext))
+    }
+
+    /// Checks that a file is a movie by its extension and, for files without
+    /// a movie extension or when sniffing, by its content, returning the
+    /// container recognized in the latter case.
+    fn check_type(&self, path: &Path) -> Result<Option<Container>, Error> {
+        if !self.is_supported(path) {
+            return Err(Error::UnsupportedExtension(path.to_path_buf()));
+        }
+        let listed = self.extensions.matches(path);
+        if listed && !self.sniff {
+            return Ok(None);
+        }
+        match Container::sniff_path(path) {
+            Ok(Some(container)) => Ok(Some(container)),
+            Ok(None) if listed => Err(Error::UnrecognizedContent(path.to_path_buf())),
+            Ok(None) => Err(Error::UnsupportedExtension(path.to_path_buf())),
+            Err(source) => Err(Error::ProbeFailed {
+                path: path.to_path_buf(),
+                source,
+            }),
+        }
+    }
+
+    /// Same as [`Scanner::process`] for a file found under `root`.
+    pub(crate) fn process_in(&self, path: &Path, root: Option<&Path>) -> Result<MovieEntry, Error> {
+        let container = self.check_type(path)?;
+        let info = match self.info_json {
+            true => InfoJson::read_for(path).map_err(|source| {
+                let path = InfoJson::sidecar_path(path);
+                Error::InvalidInfoJson { path, source }
+            })?,
+            false => None,
+        };
+        let uploader = info.as_ref().and_then(|info| info.uploader.as_deref());
+        let mut entry = self.parse(path, root, uploader)?;
+        entry.container = container;
+        let info_title = info.as_ref().and_then(|info| info.title.clone());
+        if let Some(info) = info {
+            entry.enrich_with_info_json(info);
+        }
+        if self.restore_title {
+            entry.restore_title(info_title);
+        }
+        entry.detect_date(self.strip_date);
+        if self.file_info {
+            let info =
+                FileInfo::from_path(path, root).map_err(|source| Error::UnreadableMetadata {
+                    path: path.to_path_buf(),
+                    source,
+                })?;
+            entry.file = Some(info);
+        }
+        if self.probe {
+            let info = ProbeInfo::from_path(path).map_err(|source| Error::ProbeFailed {
+                path: path.to_path_buf(),
+                source,
+            })?;
+            entry.probe = info;
+        }
+        if self.verify {
+            let verification =
+                Verification::from_path(path).map_err(|source| Error::ProbeFailed {
+                    path: path.to_path_buf(),
+                    source,
+                })?;
+            entry.integrity = Some(verification);
+        }
+        Ok(entry)
+    }
+}
+
+/// Type of a directory entry, without following symbolic links.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+}
+
+impl From<fs::FileType> for EntryKind {
+    fn from(file_type: fs::FileType) -> Self {
+        if file_type.is_symlink() {
+            Self::Symlink
+        } else if file_type.is_dir() {
+            Self::Dir
+        } else {
+            Self::File
+        }
+    }
+}
+
+/// Device and inode numbers identifying a directory.
+pub(crate) type DirId = (u64, u64);
+
+/// Directory that passed [`Scanner::check_dir`].
+pub(crate) struct Entering {
+    pub(crate) id: Option<DirId>,
+    pub(crate) mtime: Option<u64>,
+}
+
+#[cfg(unix)]
+fn dir_id(metadata: &fs::Metadata) -> Option<DirId> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_id(_metadata: &fs::Metadata) -> Option<DirId> {
+    None
+}
+
+/// Processes a file found under `root`, reusing its cached entry if it has
+/// not changed.
+///
+/// Files are checked for changes even in directories listed from the
+/// cache, since rewriting a file in place does not touch its directory.
+pub(crate) fn process_cached(
+    scanner: &Scanner,
+    path: &Path,
+    root: &Path,
+    cache: Option<&Mutex<CacheState>>,
+) -> Result<MovieEntry, Error> {
+    let Some(cache) = cache.filter(|_| scanner.is_supported(path)) else {
+        return scanner.process_in(path, Some(root));
+    };
+    let depth_rules = scanner
+        .user_rules
+        .iter()
+        .any(|rule| matches!(rule, UserRule::Depth(_)));
+    let Some(stamp) = Stamp::read(path, scanner.info_json, depth_rules.then_some(root)) else {
+        return scanner.process_in(path, Some(root));
+    };
+    let cached = cache::lock(cache).entry(path, &stamp);
+    if let Some(mut entry) = cached {
+        if let Some(file) = &mut entry.file {
+            file.relative_path = path.strip_prefix(root).ok().map(Path::to_path_buf);
+        }
+        return Ok(entry);
+    }
+    let result = scanner.process_in(path, Some(root));
+    if let Ok(entry) = &result {
+        cache::lock(cache).insert_entry(entry, stamp);
+    }
+    result
+}
+
+/// Iterator over the results of a scan, created by [`Scanner::scan`].
+///
+/// With a single job, directories are traversed depth-first in the order
+/// returned by the file system; see [`Scanner::jobs`] and [`Scanner::sort`]
+/// for other orders.
+#[derive(Debug)]
+pub struct Scan {
+    scanner: Scanner,
+    roots: vec::IntoIter<PathBuf>,
+    /// Roots entered so far.
+    scanned: Vec<PathBuf>,
+    root: PathBuf,
+    stack: Vec<Dir>,
+    cache: Option<Mutex<CacheState>>,
+    parallel: Option<Parallel>,
+    /// Results of the whole scan when sorting.
+    sorted: Option<vec::IntoIter<Result<MovieEntry, Error>>>,
+}
+
+/// Traversal running on other threads.
+#[derive(Debug)]
+struct Parallel {
+    results: mpsc::Receiver<Result<MovieEntry, Error>>,
+    walker: thread::JoinHandle<Option<Mutex<CacheState>>>,
+}
+
+/// Directory being traversed.
+#[derive(Debug)]
+struct Dir {
+    path: PathBuf,
+    id: Option<DirId>,
+    ignores: Ignores,
+    listing: Listing,
+}
+
+#[derive(Debug)]
+enum Listing {
+    /// Children read from the file system, recorded for the cache along
+    /// with the modification time of the directory when caching.
+    Live {
+        read_dir: fs::ReadDir,
+        mtime: Option<u64>,
+        children: Option<Vec<CachedChild>>,
+    },
+    /// Children from the cache, for directories that have not changed.
+    Cached(vec::IntoIter<CachedChild>),
+}
+
+impl Scan {
+    /// Makes the scan reuse results from a previous scan for unchanged files
+    /// and directories; see [`ScanCache`].
+    pub fn with_cache(mut self, cache: ScanCache) -> Self {
+        let settings = self.scanner.settings();
+        self.cache = Some(Mutex::new(CacheState::new(cache, settings)));
+        self
+    }
+
+    /// Returns the cache given to [`Scan::with_cache`] updated with the
+    /// results of this scan, to be saved for the next one.
+    ///
+    /// Previous results under the scanned roots that were not seen again
+    /// are dropped, so this should only be called once the scan is over.
+    pub fn into_cache(self) -> Option<ScanCache> {
+        let cache = match self.parallel {
+            Some(parallel) => parallel.walker.join().ok()??,
+            None => self.cache?,
+        };
+        let cache = cache.into_inner().unwrap_or_else(|e| e.into_inner());
+        Some(cache.finish(&self.scanned))
+    }
+
+    fn enter(&mut self, dir: PathBuf) -> Result<(), Error> {
+        let ancestors = self
+            .stack
+            .iter()
+            .filter_map(|dir| dir.id)
+            .collect::<Vec<_>>();
+        let Some(Entering { id, mtime }) = self.scanner.check_dir(&dir, &ancestors)? else {
+            return Ok(());
+        };
+        // The modification time is taken before reading the directory so
+        // that changes made while reading it invalidate the listing.
+        let mtime = self.cache.as_ref().and(mtime);
+        let cached = match (&self.cache, mtime) {
+            (Some(cache), Some(mtime)) => cache::lock(cache).listing(&dir, mtime),
+            _ => None,
+        };
+        let listing = match cached {
+            Some(children) => Listing::Cached(children.into_iter()),
+            None => match fs::read_dir(&dir) {
+                Ok(read_dir) => Listing::Live {
+                    read_dir,
+                    mtime,
+                    children: mtime.map(|_| Vec::new()),
+                },
+                Err(source) => return Err(Error::UnreadableDir { path: dir, source }),
+            },
+        };
+        let parent = self.stack.last().map(|dir| dir.ignores.clone());
+        let (ignores, error) = self.scanner.ignores(&dir, &parent.unwrap_or_default());
+        self.stack.push(Dir {
+            path: dir,
+            id,
+            ignores,
+            listing,
+        });
+        error.map_or(Ok(()), Err)
+    }
+
+    /// Starts traversing all roots on other threads.
+    fn start_parallel(&mut self) -> &Parallel {
+        let roots = self.roots.by_ref().collect::<Vec<_>>();
+        self.scanned.extend(roots.iter().cloned());
+        let scanner = self.scanner.clone();
+        let cache = self.cache.take();
+        let (tx, results) = mpsc::channel();
+        let walker = thread::spawn(move || {
+            walk::walk(&scanner, scanner.jobs, &roots, cache.as_ref(), &tx);
+            cache
+        });
+        self.parallel.insert(Parallel { results, walker })
+    }
+
+    fn next_unsorted(&mut self) -> Option<Result<MovieEntry, Error>> {
+        if self.scanner.jobs > 1 {
+            let parallel = match &self.parallel {
+                Some(parallel) => parallel,
+                None => self.start_parallel(),
+            };
+            return parallel.results.recv().ok();
+        }
+        loop {
+            let Some(dir) = self.stack.last_mut() else {
+                let root = self.roots.next()?;
+                self.root = root.clone();
+                self.scanned.push(root.clone());
+                if let Err(e) = self.enter(root) {
+                    return Some(Err(e));
+                }
+                continue;
+            };
+            let (path, kind) = match &mut dir.listing {
+                Listing::Live {
+                    read_dir, children, ..
+                } => match read_dir.next() {
+                    Some(Ok(entry)) => {
+                        let path = entry.path();
+                        let kind = match entry.file_type() {
+                            Ok(file_type) => EntryKind::from(file_type),
+                            Err(source) => {
+                                *children = None;
+                                return Some(Err(Error::UnreadableMetadata { path, source }));
+                            }
+                        };
+                        match (children.as_mut(), entry.file_name().into_string()) {
+                            (Some(children), Ok(name)) => {
+                                children.push(CachedChild { name, kind });
+                            }
+                            // Names that are not valid UTF-8 cannot be cached.
+                            _ => *children = None,
+                        }
+                        (path, kind)
+                    }
+                    Some(Err(source)) => {
+                        let path = dir.path.clone();
+                        self.stack.pop();
+                        return Some(Err(Error::UnreadableDir { path, source }));
+                    }
+                    None => {
+                        let dir = self.stack.pop().unwrap();
+                        if let (
+                            Some(cache),
+                            Listing::Live {
+                                mtime: Some(mtime),
+                                children: Some(children),
+                                ..
+                            },
+                        ) = (&self.cache, dir.listing)
+                        {
+                            cache::lock(cache).insert_listing(&dir.path, mtime, children);
+                        }
+                        continue;
+                    }
+                },
+                Listing::Cached(children) => match children.next() {
+                    Some(child) => (dir.path.join(child.name), child.kind),
+                    None => {
+                        self.stack.pop();
+                        continue;
+                    }
+                },
+            };
+            let ignores = &self.stack.last().expect("directory being listed").ignores;
+            match self.scanner.resolve(&path, kind, &self.root, ignores) {
+                Ok(Some(true)) => {
+                    if let Err(e) = self.enter(path) {
+                        return Some(Err(e));
+                    }
+                    continue;
+                }
+                Ok(Some(false)) => {}
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+            let cache = self.cache.as_ref();
+            return Some(process_cached(&self.scanner, &path, &self.root, cache));
+        }
+    }
+}
+
+impl Iterator for Scan {
+    type Item = Result<MovieEntry, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.scanner.sort {
+            return self.next_unsorted();
+        }
+        if self.sorted.is_none() {
+            let mut results = std::iter::from_fn(|| self.next_unsorted()).collect::<Vec<_>>();
+            results.sort_by(|a, b| result_path(a).cmp(result_path(b)));
+            self.sorted = Some(results.into_iter());
+        }
+        self.sorted.as_mut()?.next()
+    }
+}
+
+fn result_path(result: &Result<MovieEntry, Error>) -> &Path {
+    match result {
+        Ok(entry) => &entry.path,
+        Err(e) => e.path(),
+    }
+}
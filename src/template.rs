@@ -0,0 +1,253 @@
+use std::{fmt, sync::LazyLock};
+
+use regex::Regex;
+
+/// Patterns for the `id` field of yt-dlp style output templates, tried in
+/// order: IDs of 11 characters as on YouTube, then IDs without `-` or `_`,
+/// then any run of word characters and dashes.
+///
+/// Titles may contain the same separators as IDs, so the first pattern
+/// that matches decides where the title ends. For example, `Foo-ab-cd_efghi`
+/// with `%(title)s-%(id)s` has the ID `ab-cd_efghi`.
+const ID_PATTERNS: [&str; 3] = [r"[\w-]{11}", r"[^\W_]+", r"[\w-]+"];
+
+/// Named pattern for extracting movie information from file stems.
+///
+/// A template is either a regex with named captures or a yt-dlp style output
+/// template such as `%(title)s [%(id)s]`. Either way, the `id` field is
+/// required and `title` and `upload_date` are picked up when present.
+#[derive(Debug, Clone)]
+pub struct Template {
+    name: String,
+    /// Regexes tried in order, the first matching one winning.
+    regexes: Vec<Regex>,
+}
+
+/// Fields captured by a [`Template`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateMatch<'a> {
+    pub id: &'a str,
+    pub title: &'a str,
+    pub upload_date: Option<&'a str>,
+}
+
+impl Template {
+    /// Name of the built-in template for `<title> [<id>]`.
+    pub const BRACKET: &'static str = "bracket";
+
+    /// Built-in templates selectable by name, in the form of
+    /// `(name, yt-dlp output template)`.
+    pub const PRESETS: [(&'static str, &'static str); 4] = [
+        (Self::BRACKET, "%(title)s [%(id)s]"),
+        ("id-title", "%(id)s - %(title)s"),
+        ("date-title-id", "%(upload_date)s_%(title)s_%(id)s"),
+        ("title-id", "%(title)s-%(id)s"),
+    ];
+
+    /// Creates a template from a regex with named captures.
+    pub fn from_regex(name: &str, pattern: &str) -> Result<Self, TemplateError> {
+        Self::from_regexes(name, [pattern])
+    }
+
+    fn from_regexes<I, S>(name: &str, patterns: I) -> Result<Self, TemplateError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let regexes = patterns
+            .into_iter()
+            .map(|pattern| Regex::new(pattern.as_ref()).map_err(TemplateError::Regex))
+            .collect::<Result<Vec<_>, _>>()?;
+        if !regexes
+            .iter()
+            .all(|regex| regex.capture_names().any(|n| n == Some("id")))
+        {
+            return Err(TemplateError::MissingId);
+        }
+        let name = name.to_owned();
+        Ok(Self { name, regexes })
+    }
+
+    /// Creates a template from a yt-dlp style output template.
+    ///
+    /// A trailing `.%(ext)s` is ignored since templates are matched against
+    /// file stems. Where a title ends and the ID begins is decided by the
+    /// shape of the ID; see [`Template::captures`].
+    pub fn from_format(name: &str, format: &str) -> Result<Self, TemplateError> {
+        static FIELD: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r"%\((?<field>[a-z_]+)\)s").unwrap());
+
+        let format = format.strip_suffix(".%(ext)s").unwrap_or(format);
+        let pattern = |id_pattern: &str| {
+            let mut pattern = String::from("^");
+            let mut last = 0;
+            for caps in FIELD.captures_iter(format) {
+                let whole = caps.get(0).unwrap();
+                pattern.push_str(&regex::escape(&format[last..whole.start()]));
+                let field = &caps["field"];
+                let field_pattern = match field {
+                    "id" => id_pattern,
+                    "title" => r".+",
+                    "upload_date" => r"\d{8}",
+                    _ => r".+?",
+                };
+                pattern.push_str(&format!("(?<{field}>{field_pattern})"));
+                last = whole.end();
+            }
+            pattern.push_str(&regex::escape(&format[last..]));
+            pattern.push('$');
+            pattern
+        };
+        Self::from_regexes(name, ID_PATTERNS.map(pattern))
+    }
+
+    /// Creates a template from a specification given on the command line or
+    /// in a config file.
+    ///
+    /// Specifications containing `%(` are treated as yt-dlp output templates
+    /// and anything else as regexes.
+    pub fn parse(name: &str, spec: &str) -> Result<Self, TemplateError> {
+        if spec.contains("%(") {
+            Self::from_format(name, spec)
+        } else {
+            Self::from_regex(name, spec)
+        }
+    }
+
+    /// Returns the built-in template with the given name.
+    pub fn preset(name: &str) -> Option<Self> {
+        if name == Self::BRACKET {
+            return Some(Self::bracket());
+        }
+        let (name, format) = Self::PRESETS.iter().find(|(n, _)| *n == name)?;
+        Self::from_format(name, format).ok()
+    }
+
+    /// Returns the default template for `<title> [<id>]`, also accepting
+    /// full-width brackets `［<id>］` and trailing whitespace such as
+    /// full-width spaces.
+    pub fn bracket() -> Self {
+        let regex = r"(?<title>.+)\s+[\[［](?<id>[^\]］]+)[\]］]\s*$";
+        Self::from_regex(Self::BRACKET, regex).unwrap()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Matches a file stem against the template.
+    ///
+    /// For yt-dlp style output templates, IDs of 11 characters are
+    /// preferred, then IDs without `-` or `_`, so that IDs containing the
+    /// separator that precedes them are not cut short.
+    pub fn captures<'a>(&self, stem: &'a str) -> Option<TemplateMatch<'a>> {
+        let caps = self.regexes.iter().find_map(|regex| regex.captures(stem))?;
+        let id = caps.name("id")?.as_str();
+        let title = caps.name("title").map_or("", |m| m.as_str());
+        let upload_date = caps.name("upload_date").map(|m| m.as_str());
+        Some(TemplateMatch {
+            id,
+            title,
+            upload_date,
+        })
+    }
+}
+
+/// Errors in template specifications.
+#[derive(Debug)]
+pub enum TemplateError {
+    Regex(regex::Error),
+    /// The template does not capture the `id` field.
+    MissingId,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Regex(e) => write!(f, "invalid template regex: {e}"),
+            Self::MissingId => write!(f, "template does not capture `id`"),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Regex(e) => Some(e),
+            Self::MissingId => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets() {
+        let cases = [
+            (
+                "bracket",
+                "Foo - Bar [aBcDeFgHiJkL]",
+                "aBcDeFgHiJkL",
+                "Foo - Bar",
+                None,
+            ),
+            (
+                "id-title",
+                "aBcDeFgHiJkL - Foo - Bar",
+                "aBcDeFgHiJkL",
+                "Foo - Bar",
+                None,
+            ),
+            (
+                "date-title-id",
+                "20241101_Foo_Bar_sm123",
+                "sm123",
+                "Foo_Bar",
+                Some("20241101"),
+            ),
+            (
+                "date-title-id",
+                "20241101_My_Song_ab_cdEFghIJ",
+                "ab_cdEFghIJ",
+                "My_Song",
+                Some("20241101"),
+            ),
+            ("title-id", "Foo Bar-sm123", "sm123", "Foo Bar", None),
+            ("title-id", "Foo-Bar-sm123", "sm123", "Foo-Bar", None),
+            (
+                "title-id",
+                "My Song-ab-cd_efghi",
+                "ab-cd_efghi",
+                "My Song",
+                None,
+            ),
+            ("title-id", "Foo-Bar-ab_cd", "ab_cd", "Foo-Bar", None),
+        ];
+        for (name, stem, id, title, upload_date) in cases {
+            let template = Template::preset(name).unwrap();
+            let actual = template.captures(stem);
+            let expected = Some(TemplateMatch {
+                id,
+                title,
+                upload_date,
+            });
+            assert_eq!(actual, expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn format_with_ext_and_literals() {
+        let template = Template::from_format("t", "[%(id)s] %(title)s.%(ext)s").unwrap();
+        let actual = template.captures("[abc] a.b (c)").map(|m| (m.id, m.title));
+        assert_eq!(actual, Some(("abc", "a.b (c)")));
+        assert_eq!(template.captures("abc a.b"), None);
+    }
+
+    #[test]
+    fn regex_without_id_is_rejected() {
+        let actual = Template::from_regex("t", r"(?<title>.+)");
+        assert!(matches!(actual, Err(TemplateError::MissingId)));
+    }
+}
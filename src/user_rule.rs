@@ -0,0 +1,139 @@
+use std::{borrow::Cow, fmt, num::NonZeroUsize, str::FromStr};
+
+use regex::Regex;
+
+/// Rule for finding the user a movie belongs to, written as `prefix=@`,
+/// `regex=REGEX`, `depth=N`, `uploader` or `literal=NAME`.
+///
+/// Rules are tried in order and the first one that finds a user wins. The
+/// default is the single rule `prefix=@`.
+#[derive(Debug, Clone)]
+pub enum UserRule {
+    /// The innermost directory whose name starts with the prefix.
+    Prefix(String),
+    /// The innermost directory whose name matches the regex, or the `user`
+    /// capture of the regex if it has one.
+    Regex(Regex),
+    /// The directory at the given depth below the root of the scan, `1`
+    /// being the directories directly in the root.
+    Depth(NonZeroUsize),
+    /// The `uploader` in the `.info.json` sidecar.
+    Uploader,
+    /// The given name, typically a fallback such as `unknown`.
+    Literal(String),
+}
+
+impl UserRule {
+    /// Returns the user found by the rule.
+    ///
+    /// `dirs` are the names of the directories containing the movie,
+    /// outermost first, of which the last `below_root` are below the root
+    /// of the scan.
+    pub fn find(
+        &self,
+        dirs: &[Cow<str>],
+        below_root: usize,
+        uploader: Option<&str>,
+    ) -> Option<String> {
+        match self {
+            Self::Prefix(prefix) => dirs
+                .iter()
+                .rev()
+                .find(|dir| dir.starts_with(prefix.as_str()))
+                .map(|dir| dir.clone().into_owned()),
+            Self::Regex(regex) => dirs.iter().rev().find_map(|dir| {
+                let caps = regex.captures(dir)?;
+                let user = caps.name("user").map_or(dir.as_ref(), |m| m.as_str());
+                Some(user.to_owned())
+            }),
+            Self::Depth(depth) => {
+                let start = dirs.len().saturating_sub(below_root);
+                start
+                    .checked_add(depth.get() - 1)
+                    .and_then(|i| dirs.get(i))
+                    .map(|dir| dir.clone().into_owned())
+            }
+            Self::Uploader => uploader
+                .filter(|uploader| !uploader.is_empty())
+                .map(str::to_owned),
+            Self::Literal(name) => Some(name.clone()),
+        }
+    }
+}
+
+impl Default for UserRule {
+    fn default() -> Self {
+        Self::Prefix("@".to_owned())
+    }
+}
+
+impl FromStr for UserRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, value) = match s.split_once('=') {
+            Some((kind, value)) => (kind, Some(value)),
+            None => (s, None),
+        };
+        let rule = match (kind, value) {
+            ("prefix", Some(prefix)) if !prefix.is_empty() => Self::Prefix(prefix.to_owned()),
+            ("regex", Some(regex)) => Regex::new(regex)
+                .map(Self::Regex)
+                .map_err(|e| format!("invalid user rule regex: {e}"))?,
+            ("depth", Some(depth)) => depth
+                .parse()
+                .map(Self::Depth)
+                .map_err(|_| format!("invalid user rule depth: {depth:?}"))?,
+            ("uploader", None) => Self::Uploader,
+            ("literal", Some(name)) if !name.is_empty() => Self::Literal(name.to_owned()),
+            _ => return Err(format!("invalid user rule: {s:?}")),
+        };
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for UserRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Prefix(prefix) => write!(f, "prefix={prefix}"),
+            Self::Regex(regex) => write!(f, "regex={regex}"),
+            Self::Depth(depth) => write!(f, "depth={depth}"),
+            Self::Uploader => f.write_str("uploader"),
+            Self::Literal(name) => write!(f, "literal={name}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules() {
+        let dirs = ["archive", "UCabc", "@foo", "sub"].map(Cow::from);
+        let cases = [
+            ("prefix=@", Some("@foo")),
+            ("prefix=UC", Some("UCabc")),
+            (r"regex=^UC\w+$", Some("UCabc")),
+            (r"regex=^@(?<user>\w+)$", Some("foo")),
+            ("regex=^none$", None),
+            ("depth=1", Some("UCabc")),
+            ("depth=3", Some("sub")),
+            ("depth=4", None),
+            ("uploader", Some("Foo Bar")),
+            ("literal=unknown", Some("unknown")),
+        ];
+        for (spec, expected) in cases {
+            let rule: UserRule = spec.parse().unwrap();
+            assert_eq!(rule.to_string(), spec);
+            let actual = rule.find(&dirs, 3, Some("Foo Bar"));
+            assert_eq!(actual.as_deref(), expected, "{spec}");
+        }
+        assert_eq!(UserRule::Uploader.find(&dirs, 3, None), None);
+        let huge = UserRule::Depth(NonZeroUsize::MAX);
+        assert_eq!(huge.find(&dirs, 1, None), None);
+        for spec in ["prefix=", "depth=0", "regex=(", "uploader=x", "nope"] {
+            assert!(spec.parse::<UserRule>().is_err(), "{spec}");
+        }
+    }
+}
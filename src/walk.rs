@@ -0,0 +1,243 @@
+//! Parallel traversal of directory trees.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Mutex,
+    },
+};
+
+use rayon::{Scope, ThreadPoolBuilder};
+
+use crate::{
+    cache::{self, CacheState, CachedChild},
+    filter::Ignores,
+    scan::{process_cached, DirId, EntryKind},
+    Error, MovieEntry, Scanner,
+};
+
+type Results = mpsc::Sender<Result<MovieEntry, Error>>;
+
+/// Children of a directory as `(path, kind)`.
+type Children = Vec<(PathBuf, EntryKind)>;
+
+struct Walk<'a> {
+    scanner: &'a Scanner,
+    cache: Option<&'a Mutex<CacheState>>,
+    results: &'a Results,
+    /// Set once results are no longer received, to stop early.
+    cancelled: AtomicBool,
+}
+
+/// Traverses the roots on a pool of `jobs` threads, sending the results as
+/// they are ready.
+///
+/// Each directory and each file is a task of its own, and idle threads
+/// steal tasks queued by busy ones, so that slow directories do not hold up
+/// the rest of the tree.
+pub(crate) fn walk(
+    scanner: &Scanner,
+    jobs: usize,
+    roots: &[PathBuf],
+    cache: Option<&Mutex<CacheState>>,
+    results: &Results,
+) {
+    let walk = Walk {
+        scanner,
+        cache,
+        results,
+        cancelled: AtomicBool::new(false),
+    };
+    match ThreadPoolBuilder::new().num_threads(jobs).build() {
+        Ok(pool) => pool.scope(|scope| walk.roots(scope, roots)),
+        Err(_) => rayon::scope(|scope| walk.roots(scope, roots)),
+    }
+}
+
+impl<'a> Walk<'a> {
+    fn send(&self, result: Result<MovieEntry, Error>) {
+        if self.results.send(result).is_err() {
+            self.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn roots<'s>(&'s self, scope: &Scope<'s>, roots: &'s [PathBuf]) {
+        for root in roots {
+            scope.spawn(move |scope| {
+                self.dir(scope, root, root.clone(), Vec::new(), Ignores::default())
+            });
+        }
+    }
+
+    /// Traverses a directory, given the directories it was reached through
+    /// starting with its root and the ignore files in effect in its parent.
+    fn dir<'s>(
+        &'s self,
+        scope: &Scope<'s>,
+        root: &'s Path,
+        dir: PathBuf,
+        ancestors: Vec<DirId>,
+        ignores: Ignores,
+    ) {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return;
+        }
+        let entering = match self.scanner.check_dir(&dir, &ancestors) {
+            Ok(Some(entering)) => entering,
+            Ok(None) => return,
+            Err(e) => return self.send(Err(e)),
+        };
+        let mut ancestors = ancestors;
+        ancestors.extend(entering.id);
+        let (children, error) = match self.list(&dir, self.cache.and(entering.mtime)) {
+            Ok(listing) => listing,
+            Err(e) => return self.send(Err(e)),
+        };
+        let (ignores, ignore_error) = self.scanner.ignores(&dir, &ignores);
+        if let Some(e) = ignore_error {
+            self.send(Err(e));
+        }
+        for (path, kind) in children {
+            match self.scanner.resolve(&path, kind, root, &ignores) {
+                Ok(Some(true)) => {
+                    let (ancestors, ignores) = (ancestors.clone(), ignores.clone());
+                    scope.spawn(move |scope| self.dir(scope, root, path, ancestors, ignores));
+                }
+                Ok(Some(false)) => scope.spawn(move |_| {
+                    if !self.cancelled.load(Ordering::Relaxed) {
+                        let cache = self.cache;
+                        self.send(process_cached(self.scanner, &path, root, cache));
+                    }
+                }),
+                Ok(None) => {}
+                Err(e) => self.send(Err(e)),
+            }
+        }
+        if let Some(e) = error {
+            self.send(Err(e));
+        }
+    }
+
+    /// Lists the children of a directory, along with the error that ended
+    /// the listing early, if any, or fails if the directory cannot be read
+    /// at all.
+    ///
+    /// As in sequential scans, `mtime` must be taken before reading the
+    /// directory.
+    fn list(&self, dir: &Path, mtime: Option<u64>) -> Result<(Children, Option<Error>), Error> {
+        if let (Some(cache), Some(mtime)) = (self.cache, mtime) {
+            if let Some(children) = cache::lock(cache).listing(dir, mtime) {
+                let children = children
+                    .into_iter()
+                    .map(|child| (dir.join(child.name), child.kind))
+                    .collect();
+                return Ok((children, None));
+            }
+        }
+        let read_dir = match fs::read_dir(dir) {
+            Ok(read_dir) => read_dir,
+            Err(source) => {
+                let path = dir.to_path_buf();
+                return Err(Error::UnreadableDir { path, source });
+            }
+        };
+        let mut children = Vec::new();
+        let mut cached = mtime.map(|_| Vec::new());
+        for entry in read_dir {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(source) => {
+                    let path = dir.to_path_buf();
+                    return Ok((children, Some(Error::UnreadableDir { path, source })));
+                }
+            };
+            let path = entry.path();
+            let kind = match entry.file_type() {
+                Ok(file_type) => EntryKind::from(file_type),
+                Err(source) => {
+                    return Ok((children, Some(Error::UnreadableMetadata { path, source })));
+                }
+            };
+            match (cached.as_mut(), entry.file_name().into_string()) {
+                (Some(cached), Ok(name)) => cached.push(CachedChild { name, kind }),
+                _ => cached = None,
+            }
+            children.push((path, kind));
+        }
+        if let (Some(cache), Some(mtime), Some(cached)) = (self.cache, mtime, cached) {
+            cache::lock(cache).insert_listing(dir, mtime, cached);
+        }
+        Ok((children, None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::TempDir, IGNORE_FILE};
+
+    #[test]
+    fn same_results_as_sequential_scans() {
+        let root = TempDir::new("walk");
+        for (n, dir) in ["@a", "@b", "@c/sub"].into_iter().enumerate() {
+            let dir = root.join(dir);
+            fs::create_dir_all(&dir).unwrap();
+            for i in 0..5 {
+                fs::write(dir.join(format!("Title {i} [id{n}{i}].mp4")), b"").unwrap();
+            }
+            fs::write(dir.join("notes.txt"), b"").unwrap();
+            fs::write(dir.join(format!("Title 0 [id{n}0].info.json")), b"{}").unwrap();
+        }
+        fs::write(root.join(IGNORE_FILE), b"").unwrap();
+        let paths = |scanner: Scanner| {
+            scanner
+                .scan([&root])
+                .map(|result| match result {
+                    Ok(entry) => entry.path,
+                    Err(e) => e.path().to_path_buf(),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let sequential = paths(Scanner::new().sort(true));
+        assert_eq!(sequential.len(), 18);
+        assert!(sequential.is_sorted());
+        assert_eq!(paths(Scanner::new().jobs(4).sort(true)), sequential);
+
+        let mut parallel = paths(Scanner::new().jobs(4));
+        parallel.sort();
+        assert_eq!(parallel, sequential);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let root = TempDir::new("symlinks");
+        let dir = root.join("@u");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("Title [abc].mp4"), b"").unwrap();
+        symlink(&dir, dir.join("loop")).unwrap();
+        symlink(dir.join("Title [abc].mp4"), dir.join("Link [def].mp4")).unwrap();
+        symlink(dir.join("missing.mp4"), dir.join("Dangling [ghi].mp4")).unwrap();
+        let kinds = |scanner: Scanner| {
+            scanner
+                .sort(true)
+                .scan([&root])
+                .map(|result| match result {
+                    Ok(entry) => entry.id,
+                    Err(e) => e.kind().to_owned(),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let expected = ["dangling_symlink", "def", "abc", "symlink_loop"];
+        assert_eq!(kinds(Scanner::new()), expected);
+        assert_eq!(kinds(Scanner::new().jobs(4)), expected);
+        let expected = ["dangling_symlink", "def", "abc"];
+        assert_eq!(kinds(Scanner::new().follow_symlinks(false)), expected);
+    }
+}
@@ -0,0 +1,410 @@
+//! Watching of directory trees for movie files being added and removed.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    sync::{mpsc, LazyLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask, Watches};
+use regex::Regex;
+
+use crate::{filter::Ignores, scan::EntryKind, Error, MovieEntry, Scanner};
+
+const MASK: WatchMask = WatchMask::CREATE
+    .union(WatchMask::MODIFY)
+    .union(WatchMask::CLOSE_WRITE)
+    .union(WatchMask::MOVED_TO)
+    .union(WatchMask::MOVED_FROM)
+    .union(WatchMask::DELETE);
+
+type Batch = std::io::Result<Vec<inotify::Event<OsString>>>;
+
+/// Change to the movie files under watched directories.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WatchEvent {
+    /// A movie file was created or moved in and has not been written to
+    /// for the settle time.
+    Added(MovieEntry),
+    /// A movie file was deleted or moved out. The entry only has the
+    /// information extracted from the path.
+    Removed(MovieEntry),
+}
+
+/// Blocking iterator over changes to movie files, created by
+/// [`Scanner::watch`].
+///
+/// Files that are only used while downloading, such as `.part` and `.ytdl`
+/// files and the `.fNNN` and `.temp` files yt-dlp merges formats through,
+/// are ignored, as are files without a movie extension. Files and
+/// directories are selected as in scans, and excluded directories are not
+/// watched. Files already present when watching starts are not reported.
+#[derive(Debug)]
+pub struct Watch {
+    scanner: Scanner,
+    roots: Vec<PathBuf>,
+    watches: Watches,
+    dirs: HashMap<WatchDescriptor, WatchedDir>,
+    events: mpsc::Receiver<Batch>,
+    /// Files waiting to settle, with the time they were last written to.
+    pending: HashMap<PathBuf, Instant>,
+    settle: Duration,
+    ready: VecDeque<Result<WatchEvent, Error>>,
+}
+
+/// Directory being watched, with the root it was reached from and the
+/// ignore files in effect in it.
+#[derive(Debug)]
+struct WatchedDir {
+    path: PathBuf,
+    root: PathBuf,
+    ignores: Ignores,
+}
+
+impl Scanner {
+    /// Starts watching the given root directories and their subdirectories
+    /// for movie files being added and removed, using inotify.
+    pub fn watch<I, P>(&self, roots: I) -> Result<Watch, Error>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let roots = roots
+            .into_iter()
+            .map(|root| root.as_ref().to_path_buf())
+            .collect::<Vec<_>>();
+        let inotify = Inotify::init().map_err(|source| Error::WatchFailed {
+            path: roots.first().cloned().unwrap_or_default(),
+            source,
+        })?;
+        let mut watch = Watch {
+            scanner: self.clone(),
+            roots: roots.clone(),
+            watches: inotify.watches(),
+            dirs: HashMap::new(),
+            events: spawn_reader(inotify),
+            pending: HashMap::new(),
+            settle: Duration::from_secs(5),
+            ready: VecDeque::new(),
+        };
+        for root in roots {
+            let wd = watch
+                .watches
+                .add(&root, MASK)
+                .map_err(|source| Error::WatchFailed {
+                    path: root.clone(),
+                    source,
+                })?;
+            let ignores = watch.ignores(&root, &Ignores::default());
+            watch.add_subdirs(&root, &root, &ignores, false);
+            let path = root.clone();
+            let dir = WatchedDir {
+                path,
+                root,
+                ignores,
+            };
+            watch.dirs.insert(wd, dir);
+        }
+        Ok(watch)
+    }
+}
+
+/// Reads inotify events on a separate thread so that the iterator can wait
+/// for them with a timeout.
+fn spawn_reader(mut inotify: Inotify) -> mpsc::Receiver<Batch> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        loop {
+            let batch = inotify
+                .read_events_blocking(&mut buffer)
+                .map(|events| events.map(|event| event.to_owned()).collect::<Vec<_>>());
+            let failed = batch.is_err();
+            if tx.send(batch).is_err() || failed {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+impl Watch {
+    /// Sets how long a file must go without being written to before it is
+    /// reported. Defaults to 5 seconds.
+    pub fn settle(mut self, settle: Duration) -> Self {
+        self.settle = settle;
+        self
+    }
+
+    /// Returns the ignore files in effect in `dir`, given those in effect in
+    /// its parent, reporting an ignore file that cannot be read.
+    fn ignores(&mut self, dir: &Path, parent: &Ignores) -> Ignores {
+        let (ignores, error) = self.scanner.ignores(dir, parent);
+        self.ready.extend(error.map(Err));
+        ignores
+    }
+
+    /// Watches the selected subdirectories of `dir`, queueing the selected
+    /// files found in them if `queue` is set.
+    fn add_subdirs(&mut self, dir: &Path, root: &Path, ignores: &Ignores, queue: bool) {
+        let read_dir = match fs::read_dir(dir) {
+            Ok(read_dir) => read_dir,
+            Err(source) => {
+                let path = dir.to_path_buf();
+                self.ready
+                    .push_back(Err(Error::UnreadableDir { path, source }));
+                return;
+            }
+        };
+        for entry in read_dir.flatten() {
+            let path = entry.path();
+            let Ok(kind) = entry.file_type().map(EntryKind::from) else {
+                continue;
+            };
+            match self.scanner.resolve(&path, kind, root, ignores) {
+                // Symbolic links are not followed, which also avoids loops.
+                Ok(Some(true)) if kind == EntryKind::Dir => {
+                    self.add_tree(path, root.to_path_buf(), ignores, queue);
+                }
+                Ok(Some(false)) if queue => self.queue(path),
+                Err(e) if queue => self.ready.push_back(Err(e)),
+                _ => {}
+            }
+        }
+    }
+
+    /// Watches a directory and its selected subdirectories, given the
+    /// ignore files in effect in its parent.
+    fn add_tree(&mut self, dir: PathBuf, root: PathBuf, parent: &Ignores, queue: bool) {
+        match self.watches.add(&dir, MASK) {
+            Ok(wd) => {
+                let ignores = self.ignores(&dir, parent);
+                self.add_subdirs(&dir, &root, &ignores, queue);
+                let path = dir;
+                let dir = WatchedDir {
+                    path,
+                    root,
+                    ignores,
+                };
+                self.dirs.insert(wd, dir);
+            }
+            Err(source) => {
+                self.ready
+                    .push_back(Err(Error::WatchFailed { path: dir, source }));
+            }
+        }
+    }
+
+    /// Queues a file to be processed once it settles. Files that fail to
+    /// parse are queued too so that the error is reported.
+    fn queue(&mut self, path: PathBuf) {
+        let unsupported = matches!(
+            self.scanner.parse(&path, None, None),
+            Err(Error::UnsupportedExtension(_))
+        );
+        if !unsupported && !is_temporary(&path) {
+            self.pending.insert(path, Instant::now());
+        }
+    }
+
+    fn handle(&mut self, event: inotify::Event<OsString>) {
+        if event.mask.contains(EventMask::IGNORED) {
+            self.dirs.remove(&event.wd);
+            return;
+        }
+        let (Some(dir), Some(name)) = (self.dirs.get(&event.wd), event.name) else {
+            return;
+        };
+        let path = dir.path.join(name);
+        let (root, ignores) = (dir.root.clone(), dir.ignores.clone());
+        let is_dir = event.mask.contains(EventMask::ISDIR);
+        let kind = if is_dir {
+            EntryKind::Dir
+        } else {
+            EntryKind::File
+        };
+        if !matches!(
+            self.scanner.resolve(&path, kind, &root, &ignores),
+            Ok(Some(_))
+        ) {
+            return;
+        }
+        let created = EventMask::CREATE | EventMask::MOVED_TO;
+        if is_dir {
+            if event.mask.intersects(created) {
+                self.add_tree(path, root, &ignores, true);
+            }
+        } else if event
+            .mask
+            .intersects(created | EventMask::MODIFY | EventMask::CLOSE_WRITE)
+        {
+            self.queue(path);
+        } else if event
+            .mask
+            .intersects(EventMask::DELETE | EventMask::MOVED_FROM)
+        {
+            self.pending.remove(&path);
+            if !is_temporary(&path) {
+                if let Ok(entry) = self.scanner.parse(&path, Some(&root), None) {
+                    self.ready.push_back(Ok(WatchEvent::Removed(entry)));
+                }
+            }
+        }
+    }
+
+    /// Processes the pending files that have settled.
+    fn process_settled(&mut self) {
+        let now = Instant::now();
+        let mut settled = self
+            .pending
+            .iter()
+            .filter(|(_, &time)| now.duration_since(time) >= self.settle)
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        settled.sort();
+        for path in settled {
+            self.pending.remove(&path);
+            if !path.is_file() {
+                continue;
+            }
+            let root = self.roots.iter().find(|root| path.starts_with(root));
+            let result = self.scanner.process_in(&path, root.map(PathBuf::as_path));
+            self.ready.push_back(result.map(WatchEvent::Added));
+        }
+    }
+}
+
+impl Iterator for Watch {
+    type Item = Result<WatchEvent, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.ready.pop_front() {
+                return Some(event);
+            }
+            self.process_settled();
+            if !self.ready.is_empty() {
+                continue;
+            }
+            let now = Instant::now();
+            let timeout = self
+                .pending
+                .values()
+                .map(|&time| (time + self.settle).saturating_duration_since(now))
+                .min();
+            let batch = match timeout {
+                Some(timeout) => match self.events.recv_timeout(timeout) {
+                    Ok(batch) => batch,
+                    Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => return None,
+                },
+                None => self.events.recv().ok()?,
+            };
+            match batch {
+                Ok(events) => events.into_iter().for_each(|event| self.handle(event)),
+                Err(source) => {
+                    let path = self.roots.first().cloned().unwrap_or_default();
+                    return Some(Err(Error::WatchFailed { path, source }));
+                }
+            }
+        }
+    }
+}
+
+/// Returns `true` for files that only exist while a download is in
+/// progress.
+fn is_temporary(path: &Path) -> bool {
+    static FORMAT_SUFFIX: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"\.(f[0-9]+|temp)$").unwrap());
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    let stem = path.file_stem().and_then(|stem| stem.to_str());
+    matches!(extension, Some("part" | "ytdl" | "tmp" | "temp"))
+        || stem.is_some_and(|stem| FORMAT_SUFFIX.is_match(stem))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::TempDir, Filter, IGNORE_FILE};
+
+    #[test]
+    fn temporary_files() {
+        let cases = [
+            ("@u/Title [abc].mp4", false),
+            ("@u/Title [abc].mp4.part", true),
+            ("@u/Title [abc].mp4.ytdl", true),
+            ("@u/Title [abc].f137.mp4", true),
+            ("@u/Title [abc].temp.mp4", true),
+            ("@u/Title [abc].f251.webm.part", true),
+            ("@u/v1.0 [abc].mp4", false),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(is_temporary(Path::new(path)), expected, "{path}");
+        }
+    }
+
+    #[test]
+    fn added_and_removed_files() {
+        let root = TempDir::new("watch");
+        let mut watch = Scanner::new()
+            .watch([&root])
+            .unwrap()
+            .settle(Duration::from_millis(50));
+
+        let dir = root.join("@user");
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("Title [abc].mp4.part"), b"data").unwrap();
+        fs::rename(
+            dir.join("Title [abc].mp4.part"),
+            dir.join("Title [abc].mp4"),
+        )
+        .unwrap();
+        match watch.next() {
+            Some(Ok(WatchEvent::Added(entry))) => assert_eq!(entry.id, "abc"),
+            event => panic!("unexpected event: {event:?}"),
+        }
+
+        fs::remove_file(dir.join("Title [abc].mp4")).unwrap();
+        match watch.next() {
+            Some(Ok(WatchEvent::Removed(entry))) => assert_eq!(entry.title, "Title"),
+            event => panic!("unexpected event: {event:?}"),
+        }
+    }
+
+    #[test]
+    fn ignored_files_and_directories() {
+        let root = TempDir::new("watch-ignores");
+        fs::write(root.join(IGNORE_FILE), b"ignored/\n*.webm\n").unwrap();
+        fs::create_dir(root.join("excluded")).unwrap();
+        let filter = Filter::new(&["excluded/"], &[]).unwrap();
+        let mut watch = Scanner::new()
+            .filter(filter)
+            .watch([&root])
+            .unwrap()
+            .settle(Duration::from_millis(50));
+
+        for dir in ["ignored/@user", "excluded/@user", "@user"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join("ignored/@user/Title [abc].mp4"), b"data").unwrap();
+        fs::write(root.join("excluded/@user/Title [def].mp4"), b"data").unwrap();
+        fs::write(root.join("@user/Title [ghi].webm"), b"data").unwrap();
+        fs::write(root.join("@user/Title [jkl].mp4"), b"data").unwrap();
+        match watch.next() {
+            Some(Ok(WatchEvent::Added(entry))) => assert_eq!(entry.id, "jkl"),
+            event => panic!("unexpected event: {event:?}"),
+        }
+        assert!(watch.pending.is_empty());
+        let mut watched = watch
+            .dirs
+            .values()
+            .map(|dir| dir.path.strip_prefix(&root).unwrap())
+            .collect::<Vec<_>>();
+        watched.sort();
+        assert_eq!(watched, [Path::new(""), Path::new("@user")]);
+    }
+}